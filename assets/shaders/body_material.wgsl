@@ -0,0 +1,26 @@
+#import bevy_pbr::forward_io::VertexOutput
+
+struct BodyMaterial {
+    color: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> material: BodyMaterial;
+@group(1) @binding(1) var base_texture: texture_2d<f32>;
+@group(1) @binding(2) var base_sampler: sampler;
+
+@fragment
+fn fragment(
+    mesh: VertexOutput,
+) -> @location(0) vec4<f32> {
+    var color = material.color;
+
+#ifdef VERTEX_UVS
+    color = color * textureSample(base_texture, base_sampler, mesh.uv);
+#endif
+
+    // cheap fixed light so the box faces stay readable without the PBR pipeline
+    let light = normalize(vec3<f32>(0.3, 1.0, 0.5));
+    let shade = 0.6 + 0.4 * max(dot(normalize(mesh.world_normal), light), 0.0);
+
+    return vec4<f32>(color.rgb * shade, color.a);
+}
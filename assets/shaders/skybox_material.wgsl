@@ -0,0 +1,50 @@
+#import bevy_pbr::forward_io::VertexOutput
+#import bevy_pbr::mesh_view_bindings::view
+
+struct SkyboxMaterial {
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    ground_color: vec4<f32>,
+    sun_color: vec4<f32>,
+    moon_color: vec4<f32>,
+    sun_direction: vec3<f32>,
+    sun_size: f32,
+    moon_direction: vec3<f32>,
+    moon_size: f32,
+};
+
+@group(1) @binding(0) var<uniform> material: SkyboxMaterial;
+
+// soft edged disc of angular radius acos(size) around a direction
+fn disc(direction: vec3<f32>, center: vec3<f32>, size: f32) -> f32 {
+    let edge = (1.0 - size) * 0.1;
+    return smoothstep(size - edge, size + edge, dot(direction, normalize(center)));
+}
+
+@fragment
+fn fragment(
+    mesh: VertexOutput,
+) -> @location(0) vec4<f32> {
+    let direction = normalize(mesh.world_position.xyz - view.world_position);
+
+    var color: vec3<f32>;
+    if direction.y >= 0.0 {
+        color = mix(material.horizon_color.rgb, material.zenith_color.rgb, pow(direction.y, 0.5));
+    } else {
+        color = mix(material.horizon_color.rgb, material.ground_color.rgb, pow(-direction.y, 0.3));
+    }
+
+    // the sun and moon set behind the horizon
+    let above = smoothstep(-0.02, 0.02, direction.y);
+
+    let sun = max(dot(direction, normalize(material.sun_direction)), 0.0);
+    color = color + material.sun_color.rgb * pow(sun, 64.0) * 0.4 * material.sun_color.a * above;
+
+    let sun_disc = disc(direction, material.sun_direction, material.sun_size);
+    color = mix(color, material.sun_color.rgb, sun_disc * material.sun_color.a * above);
+
+    let moon_disc = disc(direction, material.moon_direction, material.moon_size);
+    color = mix(color, material.moon_color.rgb, moon_disc * material.moon_color.a * above);
+
+    return vec4<f32>(color, 1.0);
+}
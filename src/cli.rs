@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::voxel::MeshingMode;
+
+pub const USAGE: &str = "\
+usage: world [options]
+       world pregen --radius <chunks> --out <dir> [--seed <n>] [--center <x>,<z>] [--smooth]
+
+options:
+    --world <dir>       load saved chunks from a world folder, e.g. one made by pregen
+    --smooth            mesh terrain with smooth surfaces instead of cubes
+    --seed <n>          terrain seed
+    --history <n>       world edits kept to undo, defaults to 100
+    --headless          run without window or renderer, only generating chunks
+    --poi <x>,<z>       stream chunks around this point when headless (repeatable)
+    --radius <chunks>   chunks streamed on each side of a point when headless
+
+pregen:
+    --radius <chunks>   chunks generated on each side of the center chunk
+    --out <dir>         world folder chunks are saved to, resumed if it exists
+    --center <x>,<z>    center chunk, defaults to 0,0
+    --threads <n>       worker threads, defaults to the available parallelism";
+
+pub enum Command {
+    Run(Options),
+    Pregen(PregenOptions),
+}
+
+impl Command {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+
+        if args.peek().map(String::as_str) == Some("pregen") {
+            args.next();
+            PregenOptions::parse(args).map(Command::Pregen)
+        } else {
+            Options::parse(args).map(Command::Run)
+        }
+    }
+}
+
+pub struct Options {
+    pub world: Option<PathBuf>,
+    pub headless: bool,
+    pub meshing: MeshingMode,
+    pub seed: Option<u32>,
+    /// Points chunks are streamed around when headless, as world `x` and `z`.
+    pub points_of_interest: Vec<Vec2>,
+    pub radius: i32,
+    /// World edits kept to undo.
+    pub history: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            world: None,
+            headless: false,
+            meshing: MeshingMode::Blocky,
+            seed: None,
+            points_of_interest: Vec::new(),
+            radius: 4,
+            history: 100,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--world" => options.world = Some(PathBuf::from(value("--world")?)),
+                "--headless" => options.headless = true,
+                "--smooth" => options.meshing = MeshingMode::Smooth,
+                "--seed" => options.seed = Some(parse_number(&value("--seed")?)?),
+                "--radius" => options.radius = parse_number(&value("--radius")?)?,
+                "--history" => options.history = parse_number(&value("--history")?)?,
+                "--poi" => {
+                    let (x, z) = parse_pair(&value("--poi")?)?;
+                    options.points_of_interest.push(Vec2::new(x, z));
+                }
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if options.points_of_interest.is_empty() {
+            options.points_of_interest.push(Vec2::ZERO);
+        }
+
+        Ok(options)
+    }
+}
+
+pub struct PregenOptions {
+    pub out: PathBuf,
+    pub radius: i32,
+    pub center: IVec2,
+    pub seed: Option<u32>,
+    pub meshing: MeshingMode,
+    pub threads: Option<usize>,
+}
+
+impl PregenOptions {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut out = None;
+        let mut radius = None;
+        let mut center = IVec2::ZERO;
+        let mut seed = None;
+        let mut meshing = MeshingMode::Blocky;
+        let mut threads = None;
+
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--out" => out = Some(PathBuf::from(value("--out")?)),
+                "--radius" => radius = Some(parse_number(&value("--radius")?)?),
+                "--seed" => seed = Some(parse_number(&value("--seed")?)?),
+                "--smooth" => meshing = MeshingMode::Smooth,
+                "--threads" => threads = Some(parse_number(&value("--threads")?)?),
+                "--center" => {
+                    let (x, z) = parse_pair(&value("--center")?)?;
+                    center = IVec2::new(x, z);
+                }
+                _ => return Err(format!("unknown pregen argument '{}'", arg)),
+            }
+        }
+
+        Ok(Self {
+            out: out.ok_or("pregen needs --out")?,
+            radius: radius.ok_or("pregen needs --radius")?,
+            center,
+            seed,
+            meshing,
+            threads,
+        })
+    }
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str) -> Result<(T, T), String> {
+    let (x, z) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected <x>,<z>, got '{}'", value))?;
+
+    Ok((parse_number(x)?, parse_number(z)?))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse(&[]).unwrap();
+
+        assert!(!options.headless);
+        assert_eq!(options.meshing, MeshingMode::Blocky);
+        assert_eq!(options.seed, None);
+        assert_eq!(options.points_of_interest, vec![Vec2::ZERO]);
+        assert_eq!(options.history, 100);
+        assert_eq!(parse(&["--history", "20"]).unwrap().history, 20);
+    }
+
+    #[test]
+    fn headless_with_points_of_interest() {
+        let options = parse(&[
+            "--headless",
+            "--seed",
+            "12",
+            "--poi",
+            "100,-20.5",
+            "--poi",
+            "0,640",
+            "--radius",
+            "2",
+        ])
+        .unwrap();
+
+        assert!(options.headless);
+        assert_eq!(options.seed, Some(12));
+        assert_eq!(options.radius, 2);
+        assert_eq!(
+            options.points_of_interest,
+            vec![Vec2::new(100.0, -20.5), Vec2::new(0.0, 640.0)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--seed", "abc"]).is_err());
+        assert!(parse(&["--poi", "10"]).is_err());
+    }
+
+    #[test]
+    fn pregen_subcommand() {
+        let args = [
+            "pregen", "--seed", "7", "--radius", "3", "--out", "saves/a", "--center", "-2,5",
+        ];
+
+        match Command::parse(args.iter().map(|arg| arg.to_string())).unwrap() {
+            Command::Pregen(options) => {
+                assert_eq!(options.seed, Some(7));
+                assert_eq!(options.radius, 3);
+                assert_eq!(options.out, PathBuf::from("saves/a"));
+                assert_eq!(options.center, IVec2::new(-2, 5));
+                assert_eq!(options.threads, None);
+            }
+            Command::Run(_) => panic!("expected pregen"),
+        }
+
+        assert!(PregenOptions::parse(["--radius".to_string(), "3".to_string()]).is_err());
+    }
+}
@@ -1,13 +1,95 @@
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::log::LogPlugin;
 use bevy::pbr::CascadeShadowConfig;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_rapier3d::prelude::*;
 use player::*;
 
+mod cli;
 mod player;
+mod pregen;
+mod skybox;
 mod voxel;
 
 fn main() {
+    let options = match cli::Command::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Run(options)) => options,
+        Ok(cli::Command::Pregen(options)) => {
+            if let Err(error) = pregen::run(&options) {
+                eprintln!("pregen failed: {}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut world = voxel::World {
+        meshing: options.meshing,
+        history: voxel::history::History::new(options.history),
+        ..default()
+    };
+
+    if let Some(seed) = options.seed {
+        world.seed = seed;
+    }
+
+    let mut sky = None;
+
+    if let Some(dir) = &options.world {
+        match open_world(dir, &mut world) {
+            Ok(info) => sky = info.sky,
+            Err(error) => {
+                eprintln!("could not open world {}: {}", dir.display(), error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if options.headless {
+        run_headless(&options, world);
+    } else {
+        run(world, skybox::Cubemap::new(sky));
+    }
+}
+
+/// Points the world at a save folder, taking its seed and meshing mode when it has them,
+/// and returns the settings of the save.
+fn open_world(
+    dir: &std::path::Path,
+    world: &mut voxel::World,
+) -> std::io::Result<voxel::storage::WorldInfo> {
+    let info = match voxel::storage::read_info(dir)? {
+        Some(info) => {
+            world.seed = info.seed;
+            world.meshing = info.meshing;
+            info
+        }
+        None => {
+            let info = voxel::storage::WorldInfo {
+                seed: world.seed,
+                meshing: world.meshing,
+                sky: None,
+            };
+
+            std::fs::create_dir_all(dir)?;
+            voxel::storage::write_info(dir, &info)?;
+            info
+        }
+    };
+
+    world.save_dir = Some(dir.to_path_buf());
+
+    Ok(info)
+}
+
+fn run(world: voxel::World, cubemap: skybox::Cubemap) {
     App::new()
         .add_plugins((
             DefaultPlugins
@@ -23,64 +105,116 @@ fn main() {
             RapierPhysicsPlugin::<NoUserData>::default(),
             voxel::VoxelPlugins::default(),
             PlayerPlugins::default(),
+            skybox::SkyboxPlugin,
         ))
         .add_plugins(EguiPlugin)
+        .insert_resource(world)
+        .insert_resource(cubemap)
         .add_systems(Startup, setup)
         .add_systems(Update, update)
-        .add_systems(Update, update_chunks)
         .run();
 }
 
+/// Streams chunks around the points of interest without window, renderer or player.
+fn run_headless(options: &cli::Options, world: voxel::World) {
+    let mut app = App::new();
+
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        ))),
+        LogPlugin::default(),
+        voxel::VoxelPlugins { headless: true },
+    ))
+    .insert_resource(world)
+    .add_systems(Update, report_progress);
+
+    for point in &options.points_of_interest {
+        app.world.spawn((
+            Transform::from_xyz(point.x, 0.0, point.y),
+            voxel::ChunkLoader::new(options.radius),
+        ));
+    }
+
+    app.run();
+}
+
 fn setup(mut commands: Commands) {
-    commands.spawn(DirectionalLightBundle {
-        transform: Transform::from_rotation(Quat::from_rotation_x(-0.7 * std::f32::consts::PI)),
-        directional_light: DirectionalLight {
-            illuminance: 20000.0,
-            shadows_enabled: true,
+    commands.spawn((
+        DirectionalLightBundle {
+            transform: Transform::from_rotation(Quat::from_rotation_x(-0.7 * std::f32::consts::PI)),
+            directional_light: DirectionalLight {
+                illuminance: 20000.0,
+                shadows_enabled: true,
+                ..default()
+            },
+            cascade_shadow_config: CascadeShadowConfig {
+                bounds: vec![50.0, 100.0, 200.0, 500.0],
+                ..default()
+            },
             ..default()
         },
-        cascade_shadow_config: CascadeShadowConfig {
-            bounds: vec![50.0, 100.0, 200.0, 500.0],
-            ..default()
-        },
-        ..default()
-    });
+        skybox::Sun,
+    ));
 }
 
-fn update(time: Res<Time>, mut contexts: EguiContexts, frame_count: Res<bevy::core::FrameCount>) {
-    egui::Window::new("Diagnostics").show(&contexts.ctx_mut(), |ui| {
+fn update(
+    time: Res<Time>,
+    mut contexts: EguiContexts,
+    frame_count: Res<bevy::core::FrameCount>,
+    mut time_of_day: ResMut<skybox::TimeOfDay>,
+) {
+    egui::Window::new("Diagnostics").show(contexts.ctx_mut(), |ui| {
         ui.label(format!("Frame count: {}", frame_count.0));
         ui.label(format!(
             "Frame time: {:.2} ms",
             time.delta_seconds_f64() * 1000.0
         ));
         ui.label(format!("FPS: {:.2}", 1.0 / time.delta_seconds_f64()));
+
+        ui.separator();
+
+        // edit copies so the sky is only updated when a control is used
+        let mut paused = time_of_day.paused;
+        let mut hour = time_of_day.hour;
+        let mut day_length = time_of_day.day_length;
+
+        ui.label(format!(
+            "Time: {:02}:{:02}",
+            hour as u32,
+            (hour.fract() * 60.0) as u32
+        ));
+        if ui.checkbox(&mut paused, "Pause time").changed() {
+            time_of_day.paused = paused;
+        }
+        if ui
+            .add(egui::Slider::new(&mut hour, 0.0..=24.0).text("Hour"))
+            .changed()
+        {
+            time_of_day.hour = hour.rem_euclid(24.0);
+        }
+        if ui
+            .add(egui::Slider::new(&mut day_length, 10.0..=3600.0).text("Day length (s)"))
+            .changed()
+        {
+            time_of_day.day_length = day_length;
+        }
     });
 }
 
-fn update_chunks(
-    mut world: ResMut<voxel::World>,
-    query_player: Query<&Transform, With<Player>>,
-    mut last_chunk: Local<IVec3>,
-) {
-    if query_player.is_empty() {
+fn report_progress(time: Res<Time>, world: Res<voxel::World>, mut elapsed: Local<f32>) {
+    *elapsed += time.delta_seconds();
+
+    if *elapsed < 5.0 {
         return;
     }
 
-    let player_pos = query_player.single().translation;
+    *elapsed = 0.0;
 
-    let chunk_pos = IVec3::new(
-        (player_pos.x / voxel::CHUNK_SIZE as f32).floor() as i32,
-        0,
-        (player_pos.z / voxel::CHUNK_SIZE as f32).floor() as i32,
+    info!(
+        "{} chunks generated, {} generating, {} queued",
+        world.chunks.len() - world.chunks_threads.len(),
+        world.chunks_threads.len(),
+        world.chunks_to_load.len()
     );
-
-    if *last_chunk != chunk_pos {
-        for x in -4..5 {
-            for z in -4..5 {
-                world.load_chunk(chunk_pos + IVec3::new(x, 0, z));
-            }
-        }
-        *last_chunk = chunk_pos;
-    }
 }
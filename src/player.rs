@@ -1,52 +1,175 @@
 use bevy::{
-    input::mouse::MouseMotion,
+    ecs::system::SystemParam,
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
     prelude::*,
     window::{CursorGrabMode, PrimaryWindow},
 };
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::voxel::{
+    self, block,
+    body::{
+        animation::{apply_animations, BodyAnimator, BodyClip},
+        model::BodyModel,
+        BodyBundle, BodyMaterial, BodyPart,
+    },
+    ChunkLoader, CHUNK_SIZE,
+};
+
+pub mod input;
+pub mod inventory;
+pub mod movement;
+mod save;
+
+use input::{Action, ActionState, InputMap};
+use movement::{accelerate, Contacts, Motion, MoveInput, MovementSettings};
 
 #[derive(Component)]
 pub struct Player {
-    velocity: Vec3,
-    can_jump: bool,
+    motion: Motion,
     camera_pivote: Vec3,
     camera_rotation: Vec2,
+    /// Distance the orbit camera is zoomed to.
     camera_distance: f32,
+    /// Distance the orbit camera is at, closer when something is in the way.
+    camera_current_distance: f32,
+    /// Flying without gravity, still colliding with the terrain.
+    flying: bool,
+    /// Placed on the ground at the `SpawnPoint`. The player does not move until then.
+    spawned: bool,
 }
 
 impl Default for Player {
     fn default() -> Self {
         Self {
-            velocity: Vec3::ZERO,
-            can_jump: false,
+            motion: Motion::default(),
             camera_pivote: Vec3::ZERO,
             camera_rotation: Vec2::ZERO,
             camera_distance: 0.0,
+            camera_current_distance: 0.0,
+            flying: false,
+            spawned: false,
         }
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ControlsSettings {
     pub mouse_sensitivity: f32,
+    /// Radians per second the camera turns with a stick pushed all the way.
+    pub gamepad_sensitivity: f32,
     pub movement_speed: f32,
     pub run_speed: f32,
+    pub bindings: InputMap,
+    pub min_camera_distance: f32,
+    pub max_camera_distance: f32,
+    /// Distance the orbit camera zooms per mouse wheel step.
+    pub zoom_speed: f32,
+    /// How fast the orbit camera eases to its distance, higher is snappier.
+    pub camera_smoothing: f32,
+    pub fly_speed: f32,
+    /// Change of flying speed per second.
+    pub fly_acceleration: f32,
+    pub spectator_speed: f32,
+    pub swim_speed: f32,
+    /// Part of the gravity water cancels when the player is fully submerged.
+    pub buoyancy: f32,
+    /// Part of the velocity lost per second in water.
+    pub water_drag: f32,
+    pub movement: MovementSettings,
 }
 
 impl Default for ControlsSettings {
     fn default() -> Self {
         Self {
             mouse_sensitivity: 0.25,
+            gamepad_sensitivity: 3.0,
             movement_speed: 10.0,
             run_speed: 2.5,
+            bindings: InputMap::default(),
+            min_camera_distance: 2.0,
+            max_camera_distance: 30.0,
+            zoom_speed: 1.5,
+            camera_smoothing: 8.0,
+            fly_speed: 15.0,
+            fly_acceleration: 60.0,
+            spectator_speed: 40.0,
+            swim_speed: 4.0,
+            buoyancy: 1.5,
+            water_drag: 2.0,
+            movement: MovementSettings::default(),
         }
     }
 }
 
+/// Column the player spawns in, and is brought back to after falling out of the world.
+#[derive(Resource)]
+pub struct SpawnPoint {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self { x: 32, z: 32 }
+    }
+}
+
+impl SpawnPoint {
+    /// Where the player waits, above the top of the world, for the spawn column to load.
+    pub fn held_position(&self) -> Vec3 {
+        Vec3::new(
+            self.x as f32 + 0.5,
+            CHUNK_SIZE as f32 + FEET_HEIGHT,
+            self.z as f32 + 0.5,
+        )
+    }
+}
+
+/// Height below which the player respawns.
+const KILL_PLANE: f32 = -64.0;
+
+/// Height of the bottom of the player's collider below its centre.
+const FEET_HEIGHT: f32 = 1.0;
+
+/// Height of the player's eyes above the centre of its collider.
+const EYE_HEIGHT: f32 = 0.75;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 enum Mode {
     Free = 0,
     Orbit = 1,
-    Fly = 2,
+    /// Camera flying on its own through the terrain, chunks loading around it.
+    Spectator = 2,
+    FirstPerson = 3,
+}
+
+impl Mode {
+    /// Mode the camera cycles to. The cursor stays released in `Free`.
+    fn next(self) -> Mode {
+        match self {
+            Mode::Free => Mode::Free,
+            Mode::Orbit => Mode::FirstPerson,
+            Mode::FirstPerson => Mode::Spectator,
+            Mode::Spectator => Mode::Orbit,
+        }
+    }
+}
+
+/// Clips played on the player body depending on how it moves.
+#[derive(Resource)]
+pub struct PlayerClips {
+    pub idle: Handle<BodyClip>,
+    pub walk: Handle<BodyClip>,
+}
+
+/// The character model of the player, a child of the player entity so it can turn while
+/// the collider stays upright.
+#[derive(Component, Default)]
+pub struct PlayerBody {
+    yaw: f32,
 }
 
 #[derive(Component)]
@@ -59,18 +182,58 @@ pub struct PlayerPlugins;
 
 impl Plugin for PlayerPlugins {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
-        app.add_systems(Update, (update_player, player_movement_result));
+        app.add_systems(Startup, (setup, save::load_player));
+        app.add_systems(Last, save::save_player);
+        app.add_systems(
+            Update,
+            (
+                save::restore_player.before(spawn_player),
+                spawn_player,
+                update_player.after(spawn_player),
+                animate_player,
+                look_player_head.after(apply_animations),
+                hide_player_body,
+                underwater_fog,
+            ),
+        );
         app.add_systems(PostUpdate, update_camera);
-        app.init_resource::<ControlsSettings>();
+        app.add_systems(
+            PreUpdate,
+            input::update_actions.after(bevy::input::InputSystem),
+        );
+        app.add_systems(Update, input::controls_window);
+        app.add_systems(
+            Update,
+            (
+                inventory::select_slot,
+                inventory::use_blocks,
+                inventory::hotbar_window,
+            ),
+        );
+
+        let settings = ControlsSettings::load(std::path::Path::new(input::CONTROLS_PATH))
+            .unwrap_or_else(|error| {
+                warn!("could not load {}: {}", input::CONTROLS_PATH, error);
+                ControlsSettings::default()
+            });
+        app.insert_resource(settings);
+        app.init_resource::<ActionState>();
+        app.init_resource::<SpawnPoint>();
     }
 }
 
 fn setup(
+    spawn: Res<SpawnPoint>,
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<BodyMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut clips: ResMut<Assets<BodyClip>>,
 ) {
+    commands.insert_resource(PlayerClips {
+        idle: clips.add(BodyClip::idle()),
+        walk: clips.add(BodyClip::walk()),
+    });
+
     commands.spawn((
         Camera3dBundle {
             transform: Transform::from_xyz(-20.0, 34.0, -28.0).looking_at(Vec3::ZERO, Vec3::Y),
@@ -87,45 +250,87 @@ fn setup(
             ),
         },
         PlayerCamera { mode: Mode::Free },
+        // keeps the terrain around the camera when it flies away in spectator mode
+        ChunkLoader::default(),
     ));
 
-    commands.spawn((
-        RigidBody::KinematicPositionBased,
-        Collider::capsule(-Vec3::Y / 2.0, Vec3::Y / 2.0, 0.5),
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Capsule {
-                radius: 0.5,
-                depth: 1.0,
+    let body = commands
+        .spawn((
+            BodyBundle {
+                material: materials.add(BodyMaterial {
+                    color: Color::rgb_u8(124, 144, 255),
+                    ..default()
+                }),
                 ..default()
-            })),
-            material: materials.add(Color::rgb_u8(124, 144, 255).into()),
-            transform: Transform::from_xyz(32.0, 62.0, 32.0),
-            ..default()
-        },
-        Player {
-            camera_pivote: Vec3::ZERO,
-            camera_rotation: Vec2::ZERO,
-            camera_distance: 15.0,
-            ..default()
-        },
-        KinematicCharacterController {
-            autostep: Some(CharacterAutostep {
-                max_height: CharacterLength::Absolute(1.5),
-                min_width: CharacterLength::Absolute(1.5),
+            },
+            asset_server.load::<BodyModel>("models/player.body.json"),
+            BodyAnimator::new(),
+            PlayerBody::default(),
+        ))
+        .id();
+
+    commands
+        .spawn((
+            RigidBody::KinematicPositionBased,
+            Collider::capsule(-Vec3::Y / 2.0, Vec3::Y / 2.0, 0.5),
+            // held above the spawn column until the ground there is loaded
+            SpatialBundle::from_transform(Transform::from_translation(spawn.held_position())),
+            Player {
+                camera_pivote: Vec3::ZERO,
+                camera_rotation: Vec2::ZERO,
+                camera_distance: 15.0,
+                camera_current_distance: 15.0,
                 ..default()
-            }),
-            ..default()
-        },
-    ));
+            },
+            ChunkLoader::default(),
+            inventory::Inventory::default(),
+            KinematicCharacterController {
+                autostep: Some(CharacterAutostep {
+                    max_height: CharacterLength::Absolute(1.5),
+                    min_width: CharacterLength::Absolute(1.5),
+                    ..default()
+                }),
+                ..default()
+            },
+        ))
+        .add_child(body);
+}
+
+#[derive(SystemParam)]
+struct CameraInput<'w, 's> {
+    actions: Res<'w, ActionState>,
+    mouse_motion_events: EventReader<'w, 's, MouseMotion>,
+    mouse_wheel_events: EventReader<'w, 's, MouseWheel>,
+}
+
+/// Radius of the ball cast from the pivot to keep the orbit camera out of the terrain.
+const CAMERA_RADIUS: f32 = 0.25;
+
+/// Distance of the orbit camera after easing towards `desired`, or right in front of an
+/// `obstruction` between it and the pivot.
+fn orbit_distance(
+    current: f32,
+    desired: f32,
+    obstruction: Option<f32>,
+    smoothing: f32,
+    delta: f32,
+) -> f32 {
+    let target = obstruction.map_or(desired, |distance| distance.min(desired));
+
+    // pulling in at once keeps the camera from going through the terrain
+    if obstruction.is_some() && target < current {
+        return target;
+    }
+
+    current + (target - current) * (1.0 - (-smoothing * delta).exp())
 }
 
 fn update_camera(
     mut camera_query: Query<(&mut Transform, &mut PlayerCamera), With<PlayerCamera>>,
     mut player_query: Query<(&mut Transform, &mut Player), (With<Player>, Without<PlayerCamera>)>,
     mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
-    input: Res<Input<KeyCode>>,
-    mouse: Res<Input<MouseButton>>,
-    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut input: CameraInput,
+    rapier_context: Res<RapierContext>,
     time: Res<Time>,
     settings: Res<ControlsSettings>,
     mut yaw: Local<f32>,
@@ -133,11 +338,26 @@ fn update_camera(
 ) {
     let mut mouse_rel: Vec2 = Vec2::ZERO;
 
-    for event in mouse_motion_events.read() {
+    for event in input.mouse_motion_events.read() {
         mouse_rel += event.delta;
     }
 
-    let mouse_rel_dt = mouse_rel * time.delta_seconds() * settings.mouse_sensitivity;
+    let mut wheel = 0.0;
+
+    for event in input.mouse_wheel_events.read() {
+        wheel += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 40.0,
+        };
+    }
+
+    let actions = &input.actions;
+
+    // sticks turn the camera like the mouse does, up being a negative mouse delta
+    let stick = actions.look() * Vec2::new(1.0, -1.0);
+
+    let mouse_rel_dt = mouse_rel * time.delta_seconds() * settings.mouse_sensitivity
+        + stick * time.delta_seconds() * settings.gamepad_sensitivity;
 
     let (mut camera_transform, mut camera) = camera_query.single_mut();
 
@@ -149,9 +369,13 @@ fn update_camera(
 
     let mut window = primary_window.single_mut();
 
+    if actions.just_pressed(Action::CycleCamera) {
+        camera.mode = camera.mode.next();
+    }
+
     match camera.mode {
         Mode::Free => {
-            if mouse.pressed(MouseButton::Left) {
+            if actions.pressed(Action::GrabCursor) {
                 camera.mode = Mode::Orbit;
 
                 window.cursor.grab_mode = CursorGrabMode::Locked;
@@ -159,19 +383,16 @@ fn update_camera(
             }
         }
         Mode::Orbit => {
-            if input.pressed(KeyCode::Escape) {
+            if actions.pressed(Action::ReleaseCursor) {
                 camera.mode = Mode::Free;
 
                 window.cursor.grab_mode = CursorGrabMode::None;
                 window.cursor.visible = true;
-            } else if input.just_pressed(KeyCode::F) {
-                camera.mode = Mode::Fly;
-
-                window.cursor.grab_mode = CursorGrabMode::Locked;
-                window.cursor.visible = false;
+            } else if actions.just_pressed(Action::ToggleSpectator) {
+                camera.mode = Mode::Spectator;
             }
 
-            if mouse_rel.length() > 0.0 {
+            if mouse_rel_dt.length() > 0.0 {
                 player.camera_rotation.y -= mouse_rel_dt.x;
                 player.camera_rotation.x -= mouse_rel_dt.y;
 
@@ -182,47 +403,88 @@ fn update_camera(
                 + player_transform.up() * 1.5
                 + Quat::from_rotation_y(player.camera_rotation.y) * player_transform.right() * 0.5;
 
-            camera_transform.translation = player.camera_pivote
-                + Quat::from_rotation_y(player.camera_rotation.y)
-                    * Quat::from_rotation_x(player.camera_rotation.x)
-                    * Vec3::new(0.0, 0.0, player.camera_distance);
+            player.camera_distance = (player.camera_distance - wheel * settings.zoom_speed)
+                .clamp(settings.min_camera_distance, settings.max_camera_distance);
+
+            let direction = Quat::from_rotation_y(player.camera_rotation.y)
+                * Quat::from_rotation_x(player.camera_rotation.x)
+                * Vec3::Z;
+
+            // only the terrain blocks the view, not the player
+            let obstruction = rapier_context
+                .cast_shape(
+                    player.camera_pivote,
+                    Quat::IDENTITY,
+                    direction,
+                    &Collider::ball(CAMERA_RADIUS),
+                    player.camera_distance,
+                    QueryFilter::only_fixed(),
+                )
+                .map(|(_, toi)| toi.toi);
+
+            player.camera_current_distance = orbit_distance(
+                player.camera_current_distance,
+                player.camera_distance,
+                obstruction,
+                settings.camera_smoothing,
+                time.delta_seconds(),
+            );
+
+            camera_transform.translation =
+                player.camera_pivote + direction * player.camera_current_distance;
 
             camera_transform.look_at(player.camera_pivote, Vec3::Y);
             (*yaw, *pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
         }
-        Mode::Fly => {
-            if input.pressed(KeyCode::Escape) {
+        Mode::FirstPerson => {
+            if actions.pressed(Action::ReleaseCursor) {
                 camera.mode = Mode::Free;
 
                 window.cursor.grab_mode = CursorGrabMode::None;
                 window.cursor.visible = true;
-            } else if input.just_pressed(KeyCode::F) {
-                camera.mode = Mode::Orbit;
-
-                window.cursor.grab_mode = CursorGrabMode::Locked;
-                window.cursor.visible = false;
+            } else if actions.just_pressed(Action::ToggleSpectator) {
+                camera.mode = Mode::Spectator;
             }
 
-            if mouse_rel.length() > 0.0 {
+            if mouse_rel_dt.length() > 0.0 {
                 player.camera_rotation.y -= mouse_rel_dt.x;
                 player.camera_rotation.x -= mouse_rel_dt.y;
 
                 player.camera_rotation.x = player.camera_rotation.x.clamp(-1.5, 1.5);
             }
 
-            let direction = Vec3 {
-                x: input.pressed(KeyCode::D) as i32 as f32
-                    - input.pressed(KeyCode::A) as i32 as f32,
-                y: input.pressed(KeyCode::Space) as i32 as f32
-                    - input.pressed(KeyCode::ShiftLeft) as i32 as f32,
-                z: input.pressed(KeyCode::S) as i32 as f32
-                    - input.pressed(KeyCode::W) as i32 as f32,
+            // looks the same way the orbit camera does, from the player's eyes
+            camera_transform.translation = player_transform.translation + Vec3::Y * EYE_HEIGHT;
+            camera_transform.rotation = Quat::from_rotation_y(player.camera_rotation.y)
+                * Quat::from_rotation_x(player.camera_rotation.x);
+
+            (*yaw, *pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+        }
+        Mode::Spectator => {
+            if actions.pressed(Action::ReleaseCursor) {
+                camera.mode = Mode::Free;
+
+                window.cursor.grab_mode = CursorGrabMode::None;
+                window.cursor.visible = true;
+            } else if actions.just_pressed(Action::ToggleSpectator) {
+                camera.mode = Mode::Orbit;
+            }
+
+            if mouse_rel_dt.length() > 0.0 {
+                player.camera_rotation.y -= mouse_rel_dt.x;
+                player.camera_rotation.x -= mouse_rel_dt.y;
+
+                player.camera_rotation.x = player.camera_rotation.x.clamp(-1.5, 1.5);
             }
-            .normalize();
 
-            let mouse_rel_dt = mouse_rel * time.delta_seconds() * 1.0;
+            let direction = (actions.movement()
+                + Vec3::Y * (actions.value(Action::Jump) - actions.value(Action::Descend)))
+            .clamp_length_max(1.0);
+
+            let mouse_rel_dt = mouse_rel * time.delta_seconds() * 1.0
+                + stick * time.delta_seconds() * settings.gamepad_sensitivity;
 
-            if mouse_rel.length() > 0.0 {
+            if mouse_rel_dt.length() > 0.0 {
                 *yaw -= mouse_rel_dt.x;
                 *pitch -= mouse_rel_dt.y;
 
@@ -232,68 +494,366 @@ fn update_camera(
             }
 
             if direction.length() > 0.0 {
+                let mut speed = settings.spectator_speed;
+
+                if actions.pressed(Action::Sprint) {
+                    speed *= settings.run_speed;
+                }
+
                 let direction = camera_transform.rotation * direction;
-                camera_transform.translation += direction * time.delta_seconds() * 40.0;
+                camera_transform.translation += direction * time.delta_seconds() * speed;
             }
         }
     }
 }
 
+/// Marks the camera while it is in a liquid, keeping the fog falloff to restore.
+#[derive(Component)]
+pub struct Underwater {
+    falloff: FogFalloff,
+}
+
+const UNDERWATER_FOG_COLOR: Color = Color::rgb(0.05, 0.2, 0.35);
+
+type FogQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static mut FogSettings,
+        Option<&'static Underwater>,
+    ),
+    With<PlayerCamera>,
+>;
+
+/// Tints and thickens the fog while the camera is underwater.
+fn underwater_fog(mut commands: Commands, mut camera_query: FogQuery, world: Res<voxel::World>) {
+    for (entity, transform, mut fog, underwater) in camera_query.iter_mut() {
+        let in_liquid = world
+            .block_at(transform.translation())
+            .is_some_and(block::is_liquid);
+
+        match (in_liquid, underwater) {
+            (true, None) => {
+                commands.entity(entity).insert(Underwater {
+                    falloff: fog.falloff.clone(),
+                });
+                fog.color = UNDERWATER_FOG_COLOR;
+                fog.falloff = FogFalloff::Exponential { density: 0.15 };
+            }
+            (false, Some(underwater)) => {
+                fog.falloff = underwater.falloff.clone();
+                commands.entity(entity).remove::<Underwater>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Heights above the player origin checked for water: feet, waist and eyes.
+const SUBMERSION_SAMPLES: [f32; 3] = [0.1 - FEET_HEIGHT, 0.0, EYE_HEIGHT];
+
+/// Part of the player in a liquid, from 0 on land to 1 when fully underwater.
+fn submersion(position: Vec3, block_at: impl Fn(Vec3) -> Option<u8>) -> f32 {
+    SUBMERSION_SAMPLES
+        .iter()
+        .filter(|height| block_at(position + Vec3::Y * **height).is_some_and(block::is_liquid))
+        .count() as f32
+        / SUBMERSION_SAMPLES.len() as f32
+}
+
+/// Places the player on the highest ground of the spawn column once its chunk has a
+/// collider, and again whenever they fall below the kill plane. Waits while a saved player
+/// is being restored instead.
+fn spawn_player(
+    mut player_query: Query<(&mut Transform, &mut Player)>,
+    spawn: Res<SpawnPoint>,
+    world: Res<voxel::World>,
+    restored: Option<Res<save::RestoredPlayer>>,
+) {
+    if restored.is_some() {
+        return;
+    }
+
+    for (mut transform, mut player) in player_query.iter_mut() {
+        // moved back over the spawn column first, so the chunks around it are loaded at
+        // full detail wherever the player fell
+        if transform.translation.y < KILL_PLANE {
+            transform.translation = spawn.held_position();
+            player.motion = Motion::default();
+            player.spawned = false;
+        }
+
+        if player.spawned {
+            continue;
+        }
+
+        let Some(ground) = world.ground_height(spawn.x, spawn.z) else {
+            continue;
+        };
+
+        // a little above the ground so the controller settles onto it
+        transform.translation = Vec3::new(
+            spawn.x as f32 + 0.5,
+            ground + FEET_HEIGHT + 0.1,
+            spawn.z as f32 + 0.5,
+        );
+        player.motion = Motion::default();
+        player.spawned = true;
+    }
+}
+
 fn update_player(
-    mut player_query: Query<(&mut KinematicCharacterController, &mut Player), With<Player>>,
+    mut player_query: Query<
+        (
+            &mut KinematicCharacterController,
+            &mut Player,
+            &Transform,
+            Option<&KinematicCharacterControllerOutput>,
+        ),
+        With<Player>,
+    >,
     mut camera_query: Query<&mut PlayerCamera, With<PlayerCamera>>,
-    input: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     time: Res<Time>,
     settings: Res<ControlsSettings>,
+    world: Res<voxel::World>,
 ) {
     let camera = camera_query.single_mut();
+    let (mut controller, mut player, transform, output) = player_query.single_mut();
+    let delta = time.delta_seconds();
 
-    match camera.mode {
-        Mode::Fly => return,
-        _ => (),
+    if !player.spawned || camera.mode == Mode::Spectator {
+        player.motion.velocity = Vec3::ZERO;
+        return;
+    }
+
+    if actions.just_pressed(Action::ToggleFly) {
+        player.flying = !player.flying;
+        player.motion.velocity = Vec3::ZERO;
     }
 
-    let (mut controller, mut player) = player_query.single_mut();
+    if player.flying {
+        let direction = (Quat::from_rotation_y(player.camera_rotation.y) * actions.movement()
+            + Vec3::Y * (actions.value(Action::Jump) - actions.value(Action::Descend)))
+        .clamp_length_max(1.0);
+
+        let mut speed = settings.fly_speed;
+
+        if actions.pressed(Action::Sprint) {
+            speed *= settings.run_speed;
+        }
+
+        player.motion.velocity = accelerate(
+            player.motion.velocity,
+            direction * speed,
+            settings.fly_acceleration,
+            delta,
+        );
 
-    let direction = Vec3 {
-        x: input.pressed(KeyCode::D) as i32 as f32 - input.pressed(KeyCode::A) as i32 as f32,
-        y: 0.0,
-        z: input.pressed(KeyCode::S) as i32 as f32 - input.pressed(KeyCode::W) as i32 as f32,
+        // the controller still keeps the player out of the terrain
+        controller.translation = Some(player.motion.velocity * delta);
+        return;
     }
-    .normalize();
 
-    let mut movment = Vec3::ZERO;
+    let submersion = submersion(transform.translation, |point| world.block_at(point));
+
+    let mut max_speed = if submersion > 0.0 {
+        settings.swim_speed
+    } else {
+        settings.movement_speed
+    };
+
+    if actions.pressed(Action::Sprint) {
+        max_speed *= settings.run_speed;
+    }
 
-    if input.pressed(KeyCode::Space) && player.can_jump {
-        player.velocity.y = 10.0;
+    let input = MoveInput {
+        direction: Quat::from_rotation_y(player.camera_rotation.y) * actions.movement(),
+        max_speed,
+        jump: actions.just_pressed(Action::Jump),
+    };
+
+    // what the controller ran into while applying the previous step
+    let contacts = output.map_or(Contacts::default(), |output| {
+        Contacts::new(
+            output.grounded,
+            output.desired_translation,
+            output.effective_translation,
+        )
+    });
+
+    player
+        .motion
+        .step(&settings.movement, input, contacts, delta);
+
+    if submersion > 0.0 {
+        let motion = &mut player.motion;
+
+        // buoyancy outweighs gravity once the player is deep enough, so they float
+        motion.velocity.y += settings.movement.gravity * settings.buoyancy * submersion * delta;
+
+        let swim = actions.value(Action::Jump) - actions.value(Action::Descend);
+
+        if swim != 0.0 {
+            motion.velocity = accelerate(
+                motion.velocity,
+                Vec3::new(
+                    motion.velocity.x,
+                    swim * settings.swim_speed,
+                    motion.velocity.z,
+                ),
+                20.0,
+                delta,
+            );
+        }
 
-        player.can_jump = false;
+        motion.velocity *= (-settings.water_drag * delta).exp();
     }
 
-    if direction.length() > 0.0 {
-        let player_direction = Quat::from_rotation_y(player.camera_rotation.y) * direction;
+    controller.translation = Some(player.motion.velocity * delta);
+}
+
+/// Plays the walk clip while the player moves, sped up when running, and idles otherwise.
+/// The body turns towards where the player walks.
+fn animate_player(
+    player_query: Query<&Player>,
+    mut body_query: Query<(&mut Transform, &mut BodyAnimator, &mut PlayerBody)>,
+    clips: Option<Res<PlayerClips>>,
+    settings: Res<ControlsSettings>,
+    time: Res<Time>,
+) {
+    let (Some(clips), Ok(player)) = (clips, player_query.get_single()) else {
+        return;
+    };
+
+    for (mut transform, mut animator, mut body) in body_query.iter_mut() {
+        let movement = Vec2::new(player.motion.velocity.x, player.motion.velocity.z);
+        let speed = movement.length();
 
-        movment = player_direction * settings.movement_speed;
+        if speed > 0.1 {
+            animator.play(clips.walk.clone(), 0.2);
+            animator.speed = (speed / settings.movement_speed).max(1.0);
 
-        if input.pressed(KeyCode::ShiftLeft) {
-            movment *= settings.run_speed;
+            // the model faces -z
+            let target = (-movement.x).atan2(-movement.y);
+            let turn = wrap_angle(target - body.yaw);
+            body.yaw += turn * (time.delta_seconds() * 10.0).min(1.0);
+        } else {
+            animator.play(clips.idle.clone(), 0.3);
+            animator.speed = 1.0;
         }
+
+        transform.rotation = Quat::from_rotation_y(body.yaw);
     }
+}
 
-    player.velocity += Vec3::Y * -20.0 * time.delta_seconds();
+/// Turns the head of the player body towards where the camera looks, as far as the neck
+/// allows.
+fn look_player_head(
+    player_query: Query<&Player>,
+    body_query: Query<&PlayerBody>,
+    mut parts_query: Query<(&BodyPart, &mut Transform)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    for (part, mut transform) in parts_query.iter_mut() {
+        if part.name != "head" {
+            continue;
+        }
 
-    controller.translation = Some((player.velocity + movment) * time.delta_seconds());
+        let Ok(body) = body_query.get(part.body) else {
+            continue;
+        };
+
+        let yaw = wrap_angle(player.camera_rotation.y - body.yaw).clamp(-1.2, 1.2);
+        let pitch = player.camera_rotation.x.clamp(-0.8, 0.8);
+
+        transform.rotation =
+            part.rest.rotation * Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch);
+    }
 }
 
-fn player_movement_result(
-    mut controller: Query<(&mut Player, &KinematicCharacterControllerOutput), With<Player>>,
+/// Hides the player body in first person, so it does not block the view.
+fn hide_player_body(
+    camera_query: Query<&PlayerCamera>,
+    mut body_query: Query<&mut Visibility, With<PlayerBody>>,
 ) {
-    for (mut player, output) in controller.iter_mut() {
-        if output.grounded {
-            player.velocity.y = 0.0;
-            player.can_jump = true;
-        } else {
-            player.can_jump = false;
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    let visibility = if camera.mode == Mode::FirstPerson {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+
+    for mut body_visibility in body_query.iter_mut() {
+        if *body_visibility != visibility {
+            *body_visibility = visibility;
+        }
+    }
+}
+
+/// Wraps an angle in radians to `[-PI, PI]`.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submersion_counts_samples_in_water() {
+        // water up to y = 10
+        let lake = |point: Vec3| {
+            Some(if point.y < 10.0 {
+                block::WATER
+            } else {
+                block::AIR
+            })
+        };
+
+        assert_eq!(submersion(Vec3::Y * 12.0, lake), 0.0);
+        assert_eq!(submersion(Vec3::Y * 10.5, lake), 1.0 / 3.0);
+        assert_eq!(submersion(Vec3::Y * 9.5, lake), 2.0 / 3.0);
+        assert_eq!(submersion(Vec3::Y * 5.0, lake), 1.0);
+
+        // unloaded chunks are not water
+        assert_eq!(submersion(Vec3::ZERO, |_| None), 0.0);
+    }
+
+    #[test]
+    fn orbit_camera_eases_to_its_distance() {
+        let mut distance = 5.0;
+
+        for _ in 0..10 {
+            let next = orbit_distance(distance, 15.0, None, 8.0, 1.0 / 60.0);
+            assert!(next > distance && next < 15.0);
+            distance = next;
+        }
+
+        for _ in 0..600 {
+            distance = orbit_distance(distance, 15.0, None, 8.0, 1.0 / 60.0);
         }
+        assert!((distance - 15.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn orbit_camera_pulls_in_when_obstructed() {
+        assert_eq!(orbit_distance(15.0, 15.0, Some(4.0), 8.0, 1.0 / 60.0), 4.0);
+
+        // once clear it moves back out smoothly
+        let next = orbit_distance(4.0, 15.0, None, 8.0, 1.0 / 60.0);
+        assert!(next > 4.0 && next < 6.0);
+
+        // an obstruction farther than the camera does not move it
+        let next = orbit_distance(4.0, 15.0, Some(10.0), 8.0, 1.0 / 60.0);
+        assert!(next > 4.0 && next < 10.0);
     }
 }
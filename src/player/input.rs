@@ -0,0 +1,566 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::Path,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use super::ControlsSettings;
+
+/// Where the controls are saved, relative to the working directory.
+pub const CONTROLS_PATH: &str = "controls.json";
+
+/// How far a stick must be pushed before it counts.
+const DEADZONE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sprint,
+    /// Goes down while flying or swimming.
+    Descend,
+    LookUp,
+    LookDown,
+    LookLeft,
+    LookRight,
+    /// Flies without gravity, still colliding with the terrain.
+    ToggleFly,
+    /// Detaches the camera to fly through the terrain.
+    ToggleSpectator,
+    /// Switches between the orbit, first person and fly cameras.
+    CycleCamera,
+    GrabCursor,
+    ReleaseCursor,
+    Break,
+    Place,
+    /// Selects the next hotbar slot.
+    HotbarNext,
+    HotbarPrevious,
+    /// Switches between the creative and survival game modes.
+    ToggleGameMode,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Sprint,
+        Action::Descend,
+        Action::LookUp,
+        Action::LookDown,
+        Action::LookLeft,
+        Action::LookRight,
+        Action::ToggleFly,
+        Action::ToggleSpectator,
+        Action::CycleCamera,
+        Action::GrabCursor,
+        Action::ReleaseCursor,
+        Action::Break,
+        Action::Place,
+        Action::HotbarNext,
+        Action::HotbarPrevious,
+        Action::ToggleGameMode,
+    ];
+}
+
+/// An input an action can be bound to. Gamepad inputs listen to every connected gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    /// One direction of a stick or trigger axis.
+    GamepadAxis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::GamepadButton(button) => format!("Pad {:?}", button),
+            Binding::GamepadAxis { axis, positive } => {
+                format!("Pad {:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+/// The bindings of each action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::*;
+
+        let axis = |axis, positive| GamepadAxis { axis, positive };
+
+        Self(BTreeMap::from([
+            (
+                MoveForward,
+                vec![Key(KeyCode::W), axis(GamepadAxisType::LeftStickY, true)],
+            ),
+            (
+                MoveBackward,
+                vec![Key(KeyCode::S), axis(GamepadAxisType::LeftStickY, false)],
+            ),
+            (
+                MoveLeft,
+                vec![Key(KeyCode::A), axis(GamepadAxisType::LeftStickX, false)],
+            ),
+            (
+                MoveRight,
+                vec![Key(KeyCode::D), axis(GamepadAxisType::LeftStickX, true)],
+            ),
+            (
+                Jump,
+                vec![Key(KeyCode::Space), GamepadButton(GamepadButtonType::South)],
+            ),
+            (
+                Sprint,
+                vec![
+                    Key(KeyCode::ShiftLeft),
+                    GamepadButton(GamepadButtonType::LeftThumb),
+                ],
+            ),
+            (
+                Descend,
+                vec![Key(KeyCode::C), GamepadButton(GamepadButtonType::East)],
+            ),
+            (LookUp, vec![axis(GamepadAxisType::RightStickY, true)]),
+            (LookDown, vec![axis(GamepadAxisType::RightStickY, false)]),
+            (LookLeft, vec![axis(GamepadAxisType::RightStickX, false)]),
+            (LookRight, vec![axis(GamepadAxisType::RightStickX, true)]),
+            (
+                ToggleFly,
+                vec![Key(KeyCode::F), GamepadButton(GamepadButtonType::North)],
+            ),
+            (
+                ToggleSpectator,
+                vec![Key(KeyCode::N), GamepadButton(GamepadButtonType::DPadDown)],
+            ),
+            (
+                CycleCamera,
+                vec![Key(KeyCode::V), GamepadButton(GamepadButtonType::DPadUp)],
+            ),
+            (
+                GrabCursor,
+                vec![
+                    Mouse(MouseButton::Left),
+                    GamepadButton(GamepadButtonType::Start),
+                ],
+            ),
+            (
+                ReleaseCursor,
+                vec![
+                    Key(KeyCode::Escape),
+                    GamepadButton(GamepadButtonType::Select),
+                ],
+            ),
+            (
+                Break,
+                vec![
+                    Mouse(MouseButton::Left),
+                    GamepadButton(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Place,
+                vec![
+                    Mouse(MouseButton::Right),
+                    GamepadButton(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                HotbarNext,
+                vec![GamepadButton(GamepadButtonType::RightTrigger)],
+            ),
+            (
+                HotbarPrevious,
+                vec![GamepadButton(GamepadButtonType::LeftTrigger)],
+            ),
+            (ToggleGameMode, vec![Key(KeyCode::G)]),
+        ]))
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.0
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+}
+
+impl ControlsSettings {
+    /// Reads the settings saved at `path`, or the defaults when there are none. Actions
+    /// missing from the file keep their default bindings.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut settings: ControlsSettings = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(default()),
+            Err(error) => return Err(error),
+        };
+
+        for (action, bindings) in InputMap::default().0 {
+            settings.bindings.0.entry(action).or_insert(bindings);
+        }
+
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        fs::write(path, json)
+    }
+}
+
+/// Actions held this frame, with how far their analog inputs are pushed.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    values: BTreeMap<Action, f32>,
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    /// How much the action is held, from 0 to 1.
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// Sets the value of every action and works out which ones were just pressed.
+    pub fn update(&mut self, values: impl IntoIterator<Item = (Action, f32)>) {
+        self.values = values.into_iter().collect();
+
+        let pressed: HashSet<Action> = self
+            .values
+            .iter()
+            .filter(|(_, value)| **value > 0.5)
+            .map(|(action, _)| *action)
+            .collect();
+
+        self.just_pressed = pressed.difference(&self.pressed).copied().collect();
+        self.pressed = pressed;
+    }
+
+    /// Walking direction on the ground plane, -z forward, no longer than 1.
+    pub fn movement(&self) -> Vec3 {
+        Vec3::new(
+            self.value(Action::MoveRight) - self.value(Action::MoveLeft),
+            0.0,
+            self.value(Action::MoveBackward) - self.value(Action::MoveForward),
+        )
+        .clamp_length_max(1.0)
+    }
+
+    /// Stick look direction, x to the right and y up.
+    pub fn look(&self) -> Vec2 {
+        Vec2::new(
+            self.value(Action::LookRight) - self.value(Action::LookLeft),
+            self.value(Action::LookUp) - self.value(Action::LookDown),
+        )
+    }
+}
+
+/// State of the connected gamepads.
+#[derive(SystemParam)]
+pub struct GamepadInput<'w> {
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+/// Reads the bound inputs into the `ActionState`. Mouse buttons are ignored while the
+/// pointer is over an egui window, and keys while typing in one.
+pub fn update_actions(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad: GamepadInput,
+    settings: Res<ControlsSettings>,
+    mut state: ResMut<ActionState>,
+    mut contexts: EguiContexts,
+) {
+    let pointer_over_ui = contexts.ctx_mut().wants_pointer_input();
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+
+    let value = |binding: &Binding| match binding {
+        Binding::Key(key) => (!typing && keys.pressed(*key)) as i32 as f32,
+        Binding::Mouse(button) => (!pointer_over_ui && mouse.pressed(*button)) as i32 as f32,
+        Binding::GamepadButton(button) => gamepad
+            .gamepads
+            .iter()
+            .any(|id| gamepad.buttons.pressed(GamepadButton::new(id, *button)))
+            as i32 as f32,
+        Binding::GamepadAxis { axis, positive } => gamepad
+            .gamepads
+            .iter()
+            .filter_map(|id| gamepad.axes.get(GamepadAxis::new(id, *axis)))
+            .map(|value| if *positive { value } else { -value })
+            .map(|value| ((value - DEADZONE) / (1.0 - DEADZONE)).clamp(0.0, 1.0))
+            .fold(0.0, f32::max),
+    };
+
+    state.update(Action::ALL.map(|action| {
+        (
+            action,
+            settings
+                .bindings
+                .bindings(action)
+                .iter()
+                .map(value)
+                .fold(0.0, f32::max),
+        )
+    }));
+}
+
+/// First input pressed this frame, to bind an action to.
+fn pressed_binding(
+    keys: &Input<KeyCode>,
+    mouse: &Input<MouseButton>,
+    gamepad_buttons: &Input<GamepadButton>,
+) -> Option<Binding> {
+    keys.get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::GamepadButton(button.button_type))
+        })
+}
+
+/// Window to tune the controls, rebind actions and save them.
+pub fn controls_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ControlsSettings>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut rebinding: Local<Option<(Action, bool)>>,
+    mut status: Local<String>,
+) {
+    // the action waiting for an input, and whether it is added to the others
+    if let Some((action, add)) = *rebinding {
+        if let Some(binding) = pressed_binding(&keys, &mouse, &gamepad_buttons) {
+            let bindings = settings.bindings.0.entry(action).or_default();
+
+            if !add {
+                bindings.clear();
+            }
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+
+            *rebinding = None;
+        }
+    }
+
+    egui::Window::new("Controls").show(contexts.ctx_mut(), |ui| {
+        let mut mouse_sensitivity = settings.mouse_sensitivity;
+        let mut gamepad_sensitivity = settings.gamepad_sensitivity;
+
+        if ui
+            .add(egui::Slider::new(&mut mouse_sensitivity, 0.01..=2.0).text("Mouse sensitivity"))
+            .changed()
+        {
+            settings.mouse_sensitivity = mouse_sensitivity;
+        }
+        if ui
+            .add(egui::Slider::new(&mut gamepad_sensitivity, 0.1..=10.0).text("Stick sensitivity"))
+            .changed()
+        {
+            settings.gamepad_sensitivity = gamepad_sensitivity;
+        }
+
+        ui.separator();
+
+        egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(format!("{:?}", action));
+
+                if rebinding.map(|(waiting, _)| waiting) == Some(action) {
+                    ui.label("Press an input...");
+                } else {
+                    let labels: Vec<String> = settings
+                        .bindings
+                        .bindings(action)
+                        .iter()
+                        .map(Binding::label)
+                        .collect();
+                    ui.label(labels.join(", "));
+                }
+
+                if ui.button("Set").clicked() {
+                    *rebinding = Some((action, false));
+                }
+                if ui.button("Add").clicked() {
+                    *rebinding = Some((action, true));
+                }
+                if ui.button("Clear").clicked() {
+                    settings.bindings.0.insert(action, vec![]);
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                *status = match settings.save(Path::new(CONTROLS_PATH)) {
+                    Ok(()) => format!("Saved to {}", CONTROLS_PATH),
+                    Err(error) => format!("Could not save: {}", error),
+                };
+            }
+            if ui.button("Defaults").clicked() {
+                *settings = default();
+                *rebinding = None;
+            }
+        });
+
+        if !status.is_empty() {
+            ui.label(status.as_str());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_action_has_a_default_binding() {
+        let map = InputMap::default();
+
+        for action in Action::ALL {
+            assert!(!map.bindings(action).is_empty(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn actions_used_together_have_distinct_default_bindings() {
+        let map = InputMap::default();
+
+        // grabbing the cursor is the only action used while it is free
+        let playing: Vec<Action> = Action::ALL
+            .into_iter()
+            .filter(|action| *action != Action::GrabCursor)
+            .collect();
+
+        for (i, a) in playing.iter().enumerate() {
+            for b in &playing[i + 1..] {
+                for binding in map.bindings(*a) {
+                    assert!(
+                        !map.bindings(*b).contains(binding),
+                        "{:?} and {:?} share {:?}",
+                        a,
+                        b,
+                        binding
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn actions_are_just_pressed_once() {
+        let mut state = ActionState::default();
+
+        state.update([(Action::Jump, 1.0)]);
+        assert!(state.pressed(Action::Jump));
+        assert!(state.just_pressed(Action::Jump));
+
+        state.update([(Action::Jump, 1.0)]);
+        assert!(state.pressed(Action::Jump));
+        assert!(!state.just_pressed(Action::Jump));
+
+        state.update([(Action::Jump, 0.2)]);
+        assert!(!state.pressed(Action::Jump));
+    }
+
+    #[test]
+    fn movement_is_clamped() {
+        let mut state = ActionState::default();
+
+        state.update([(Action::MoveForward, 1.0), (Action::MoveRight, 1.0)]);
+        assert!((state.movement().length() - 1.0).abs() < 1e-6);
+        assert!(state.movement().z < 0.0);
+
+        // half pushed sticks walk slower
+        state.update([(Action::MoveLeft, 0.5)]);
+        assert_eq!(state.movement(), Vec3::new(-0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn settings_round_trip_and_fill_missing_actions() {
+        let dir = std::env::temp_dir().join(format!("controls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("controls.json");
+
+        assert_eq!(
+            ControlsSettings::load(&path).unwrap().bindings,
+            InputMap::default()
+        );
+
+        let mut settings = ControlsSettings {
+            mouse_sensitivity: 0.5,
+            ..default()
+        };
+        settings
+            .bindings
+            .0
+            .insert(Action::Jump, vec![Binding::Mouse(MouseButton::Right)]);
+        settings.bindings.0.remove(&Action::Place);
+        settings.save(&path).unwrap();
+
+        let loaded = ControlsSettings::load(&path).unwrap();
+        assert_eq!(loaded.mouse_sensitivity, 0.5);
+        assert_eq!(
+            loaded.bindings.bindings(Action::Jump),
+            [Binding::Mouse(MouseButton::Right)]
+        );
+        assert_eq!(
+            loaded.bindings.bindings(Action::Place),
+            InputMap::default().bindings(Action::Place)
+        );
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(ControlsSettings::load(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
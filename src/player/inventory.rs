@@ -0,0 +1,493 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    input::{Action, ActionState},
+    Mode, Player, PlayerCamera, EYE_HEIGHT,
+};
+use crate::voxel::{
+    self, block,
+    edit::{self, BrushMode, EditTools, Selection, Tool},
+    schematic::Clipboard,
+};
+
+pub const HOTBAR_SIZE: usize = 9;
+
+/// Most blocks a slot holds.
+pub const STACK_SIZE: u32 = 64;
+
+/// How far from the player's eyes blocks can be broken and placed.
+const REACH: f32 = 6.0;
+
+/// Number keys selecting the hotbar slots, in order.
+const SLOT_KEYS: [KeyCode; HOTBAR_SIZE] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block: u8,
+    pub count: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Blocks are never used up, and broken ones are not collected.
+    #[default]
+    Creative,
+    Survival,
+}
+
+/// Blocks the player carries, in hotbar slots.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Inventory {
+    pub hotbar: [Option<ItemStack>; HOTBAR_SIZE],
+    pub selected: usize,
+    pub mode: GameMode,
+}
+
+impl Default for Inventory {
+    /// A full stack of every block of the registry but air.
+    fn default() -> Self {
+        let mut hotbar = [None; HOTBAR_SIZE];
+        let blocks = (0..block::BLOCKS.len() as u8).filter(|block| *block != block::AIR);
+
+        for (slot, block) in hotbar.iter_mut().zip(blocks) {
+            *slot = Some(ItemStack {
+                block,
+                count: STACK_SIZE,
+            });
+        }
+
+        Self {
+            hotbar,
+            selected: 0,
+            mode: GameMode::default(),
+        }
+    }
+}
+
+impl Inventory {
+    /// Selects the slot `steps` away from the current one, wrapping around.
+    pub fn scroll(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(HOTBAR_SIZE as i32) as usize;
+    }
+
+    /// Takes one block from the selected slot to place it. Creative slots never run out.
+    pub fn take(&mut self) -> Option<u8> {
+        let slot = &mut self.hotbar[self.selected];
+        let stack = slot.as_mut()?;
+        let block = stack.block;
+
+        if self.mode == GameMode::Survival {
+            stack.count -= 1;
+
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+
+        Some(block)
+    }
+
+    /// Adds a broken block in survival, to a stack of the same block with room or else to
+    /// the first empty slot. Returns `false` when there is no room left.
+    pub fn collect(&mut self, block: u8) -> bool {
+        if self.mode == GameMode::Creative {
+            return true;
+        }
+
+        if let Some(stack) = self
+            .hotbar
+            .iter_mut()
+            .flatten()
+            .find(|stack| stack.block == block && stack.count < STACK_SIZE)
+        {
+            stack.count += 1;
+            return true;
+        }
+
+        if let Some(slot) = self.hotbar.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(ItemStack { block, count: 1 });
+            return true;
+        }
+
+        false
+    }
+
+    /// Whether a broken block would be kept by `collect`.
+    pub fn has_room(&self, block: u8) -> bool {
+        self.mode == GameMode::Creative
+            || self.hotbar.iter().any(|slot| match slot {
+                Some(stack) => stack.block == block && stack.count < STACK_SIZE,
+                None => true,
+            })
+    }
+
+    /// Brings an inventory read from a save back in range: the selected slot inside the
+    /// hotbar, and stacks holding between one and `STACK_SIZE` blocks.
+    pub fn sanitize(&mut self) {
+        self.selected = self.selected.min(HOTBAR_SIZE - 1);
+
+        for slot in self.hotbar.iter_mut() {
+            match slot {
+                Some(stack) if stack.count == 0 => *slot = None,
+                Some(stack) => stack.count = stack.count.min(STACK_SIZE),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Whether a block would overlap the player standing at `player`.
+fn overlaps_player(position: IVec3, player: Vec3) -> bool {
+    let min = position.as_vec3();
+    let max = min + 1.0;
+
+    // the bounds of the capsule collider, shrunk a little so blocks can touch it
+    let player_min = player - Vec3::new(0.49, 0.99, 0.49);
+    let player_max = player + Vec3::new(0.49, 0.99, 0.49);
+
+    min.cmplt(player_max).all() && max.cmpgt(player_min).all()
+}
+
+/// Selects hotbar slots with the number keys, the mouse wheel and the gamepad, and
+/// switches the game mode. The wheel zooms the orbit camera instead, and the number keys
+/// type in egui text fields instead.
+pub fn select_slot(
+    mut inventory_query: Query<&mut Inventory>,
+    camera_query: Query<&PlayerCamera>,
+    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut contexts: EguiContexts,
+) {
+    let typing = contexts.ctx_mut().wants_keyboard_input();
+
+    let mut wheel = 0.0;
+
+    for event in mouse_wheel_events.read() {
+        wheel += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 40.0,
+        };
+    }
+
+    if camera_query
+        .get_single()
+        .map_or(true, |camera| camera.mode == Mode::Orbit)
+    {
+        wheel = 0.0;
+    }
+
+    for mut inventory in inventory_query.iter_mut() {
+        if let Some(slot) = SLOT_KEYS
+            .iter()
+            .position(|key| !typing && keys.just_pressed(*key))
+        {
+            inventory.selected = slot;
+        }
+
+        // scrolling down moves to the right, like most games
+        let steps = -wheel.round() as i32 + actions.just_pressed(Action::HotbarNext) as i32
+            - actions.just_pressed(Action::HotbarPrevious) as i32;
+
+        if steps != 0 {
+            inventory.scroll(steps);
+        }
+
+        if actions.just_pressed(Action::ToggleGameMode) {
+            inventory.mode = match inventory.mode {
+                GameMode::Creative => GameMode::Survival,
+                GameMode::Survival => GameMode::Creative,
+            };
+        }
+    }
+}
+
+/// Breaks the block the camera looks at, or places the selected block against it. In
+/// creative, the other editing tools are used instead.
+pub fn use_blocks(
+    mut world: ResMut<voxel::World>,
+    actions: Res<ActionState>,
+    mut tools: ResMut<EditTools>,
+    mut selection: ResMut<Selection>,
+    clipboard: Res<Clipboard>,
+    camera_query: Query<(&GlobalTransform, &PlayerCamera)>,
+    mut player_query: Query<(&Transform, &Player, &mut Inventory)>,
+) {
+    tools.aim = None;
+
+    let (Ok((camera_transform, camera)), Ok((transform, player, mut inventory))) =
+        (camera_query.get_single(), player_query.get_single_mut())
+    else {
+        return;
+    };
+
+    if !player.spawned || !matches!(camera.mode, Mode::Orbit | Mode::FirstPerson) {
+        return;
+    }
+
+    let origin = camera_transform.translation();
+
+    // the orbit camera is behind the player, so the reach starts from the player's eyes
+    let eyes = transform.translation + Vec3::Y * EYE_HEIGHT;
+    let reach = REACH + origin.distance(eyes);
+
+    tools.aim = world.raycast(origin, camera_transform.forward(), reach);
+
+    let breaking = actions.just_pressed(Action::Break);
+    let placing = actions.just_pressed(Action::Place);
+
+    let Some(hit) = tools.aim.filter(|_| breaking || placing) else {
+        return;
+    };
+
+    if inventory.mode == GameMode::Creative && tools.tool != Tool::Block {
+        use_tool(&mut world, &tools, &mut selection, &clipboard, hit, placing);
+        return;
+    }
+
+    if breaking {
+        // blocks with nowhere to go are not broken, rather than lost
+        let Some(block) = world
+            .get_block(hit.position)
+            .filter(|block| inventory.has_room(*block))
+        else {
+            return;
+        };
+
+        world.set_block(hit.position, block::AIR);
+        inventory.collect(block);
+    } else {
+        let position = hit.position + hit.normal;
+
+        // blocks replace air and liquids, never the player
+        if hit.normal == IVec3::ZERO
+            || overlaps_player(position, transform.translation)
+            || world.get_block(position).is_none_or(block::is_solid)
+        {
+            return;
+        }
+
+        if let Some(block) = inventory.take() {
+            world.set_block(position, block);
+        }
+    }
+}
+
+/// Uses an editing tool on the block hit, with the place action or else the break one.
+fn use_tool(
+    world: &mut voxel::World,
+    tools: &EditTools,
+    selection: &mut Selection,
+    clipboard: &Clipboard,
+    hit: voxel::RayHit,
+    placing: bool,
+) {
+    match tools.tool {
+        Tool::Block => {}
+        Tool::Brush if placing => {
+            edit::apply_brush(world, &tools.brush, hit.position, tools.block);
+        }
+        Tool::Brush => {
+            let brush = edit::Brush {
+                mode: BrushMode::Remove,
+                ..tools.brush
+            };
+            edit::apply_brush(world, &brush, hit.position, tools.block);
+        }
+        Tool::Select => selection.corners[placing as usize] = Some(hit.position),
+        Tool::FloodFill if placing => {
+            edit::flood_fill(
+                world,
+                hit.position + hit.normal,
+                tools.block,
+                tools.flood_limit,
+            );
+        }
+        Tool::FloodFill => {
+            edit::flood_fill(world, hit.position, block::AIR, tools.flood_limit);
+        }
+        Tool::Paste => {
+            if let Some(schematic) = clipboard.schematic.as_ref().filter(|_| placing) {
+                let origin = schematic.paste_origin(hit.position + hit.normal);
+                schematic.paste(world, origin, clipboard.include_air);
+            }
+        }
+    }
+}
+
+/// Draws the hotbar at the bottom of the screen. Clicking a slot selects it.
+pub fn hotbar_window(mut contexts: EguiContexts, mut inventory_query: Query<&mut Inventory>) {
+    let Ok(mut inventory) = inventory_query.get_single_mut() else {
+        return;
+    };
+
+    egui::Area::new("hotbar")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -12.0])
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::window(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for slot in 0..HOTBAR_SIZE {
+                        let text = match inventory.hotbar[slot] {
+                            Some(stack) => {
+                                let info = block::info(stack.block);
+                                let [r, g, b, _] = info.color.map(|c| (c * 255.0) as u8);
+                                let label = match inventory.mode {
+                                    GameMode::Creative => format!("{} {}", slot + 1, info.name),
+                                    GameMode::Survival => {
+                                        format!("{} {} x{}", slot + 1, info.name, stack.count)
+                                    }
+                                };
+
+                                egui::RichText::new(label).color(egui::Color32::from_rgb(r, g, b))
+                            }
+                            None => egui::RichText::new(format!("{} -", slot + 1)),
+                        };
+
+                        if ui
+                            .selectable_label(inventory.selected == slot, text)
+                            .clicked()
+                        {
+                            inventory.selected = slot;
+                        }
+                    }
+
+                    ui.separator();
+                    ui.label(format!("{:?}", inventory.mode));
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survival() -> Inventory {
+        Inventory {
+            hotbar: [None; HOTBAR_SIZE],
+            selected: 0,
+            mode: GameMode::Survival,
+        }
+    }
+
+    #[test]
+    fn default_hotbar_holds_the_registry_blocks() {
+        let inventory = Inventory::default();
+
+        assert_eq!(inventory.hotbar[0].unwrap().block, block::WATER);
+        assert_eq!(inventory.hotbar[1].unwrap().block, block::GRASS);
+        assert!(inventory.hotbar[block::BLOCKS.len() - 1..]
+            .iter()
+            .all(Option::is_none));
+    }
+
+    #[test]
+    fn scrolling_wraps_around() {
+        let mut inventory = Inventory::default();
+
+        inventory.scroll(-1);
+        assert_eq!(inventory.selected, HOTBAR_SIZE - 1);
+
+        inventory.scroll(3);
+        assert_eq!(inventory.selected, 2);
+    }
+
+    #[test]
+    fn creative_never_runs_out() {
+        let mut inventory = Inventory::default();
+
+        for _ in 0..STACK_SIZE * 2 {
+            assert_eq!(inventory.take(), Some(block::WATER));
+        }
+
+        assert!(inventory.collect(block::GRASS));
+        assert_eq!(inventory, Inventory::default());
+    }
+
+    #[test]
+    fn survival_uses_and_collects_blocks() {
+        let mut inventory = survival();
+
+        assert_eq!(inventory.take(), None);
+
+        assert!(inventory.collect(block::GRASS));
+        assert!(inventory.collect(block::GRASS));
+        assert_eq!(
+            inventory.hotbar[0],
+            Some(ItemStack {
+                block: block::GRASS,
+                count: 2
+            })
+        );
+
+        // full stacks spill over into the next free slot
+        inventory.hotbar[0].as_mut().unwrap().count = STACK_SIZE;
+        assert!(inventory.collect(block::GRASS));
+        assert_eq!(inventory.hotbar[1].unwrap().count, 1);
+
+        inventory.selected = 1;
+        assert_eq!(inventory.take(), Some(block::GRASS));
+        assert_eq!(inventory.hotbar[1], None);
+        assert_eq!(inventory.take(), None);
+
+        // no room left
+        inventory.hotbar = [Some(ItemStack {
+            block: block::WATER,
+            count: STACK_SIZE,
+        }); HOTBAR_SIZE];
+        assert!(!inventory.has_room(block::GRASS));
+        assert!(!inventory.collect(block::GRASS));
+
+        inventory.hotbar[3].as_mut().unwrap().count -= 1;
+        assert!(inventory.has_room(block::WATER));
+        assert!(!inventory.has_room(block::GRASS));
+    }
+
+    #[test]
+    fn saved_inventories_are_brought_in_range() {
+        let mut inventory = survival();
+        inventory.selected = 40;
+        inventory.hotbar[0] = Some(ItemStack {
+            block: block::GRASS,
+            count: 0,
+        });
+        inventory.hotbar[1] = Some(ItemStack {
+            block: block::GRASS,
+            count: 1000,
+        });
+
+        inventory.sanitize();
+
+        assert_eq!(inventory.selected, HOTBAR_SIZE - 1);
+        assert_eq!(inventory.hotbar[0], None);
+        assert_eq!(inventory.hotbar[1].unwrap().count, STACK_SIZE);
+        assert_eq!(inventory.take(), None);
+    }
+
+    #[test]
+    fn blocks_are_not_placed_inside_the_player() {
+        let player = Vec3::new(0.5, 11.0, 0.5);
+
+        assert!(overlaps_player(IVec3::new(0, 10, 0), player));
+        assert!(overlaps_player(IVec3::new(0, 11, 0), player));
+        assert!(!overlaps_player(IVec3::new(0, 9, 0), player));
+        assert!(!overlaps_player(IVec3::new(1, 10, 0), player));
+        assert!(!overlaps_player(IVec3::new(0, 12, 0), player));
+    }
+}
@@ -0,0 +1,333 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How the player walks, falls and jumps.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementSettings {
+    /// Downwards acceleration, in units per second squared.
+    pub gravity: f32,
+    /// Change of walking speed per second while on the ground.
+    pub ground_acceleration: f32,
+    /// Change of walking speed per second in the air.
+    pub air_acceleration: f32,
+    /// Change of speed per second slowing the player down on the ground without input.
+    pub friction: f32,
+    pub max_fall_speed: f32,
+    /// Height a jump reaches.
+    pub jump_height: f32,
+    /// Seconds after walking off a ledge the player can still jump.
+    pub coyote_time: f32,
+    /// Seconds a jump pressed before landing is remembered.
+    pub jump_buffer: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            gravity: 20.0,
+            ground_acceleration: 80.0,
+            air_acceleration: 20.0,
+            friction: 60.0,
+            max_fall_speed: 50.0,
+            jump_height: 2.5,
+            coyote_time: 0.12,
+            jump_buffer: 0.15,
+        }
+    }
+}
+
+impl MovementSettings {
+    /// Upwards speed reaching `jump_height`.
+    pub fn jump_velocity(&self) -> f32 {
+        (2.0 * self.gravity * self.jump_height).sqrt()
+    }
+}
+
+/// What the character controller ran into during the last move.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Contacts {
+    pub grounded: bool,
+    /// Something above stopped the player going up.
+    pub head_bump: bool,
+}
+
+impl Contacts {
+    /// Contacts of a move that wanted to go `desired` and went `effective`.
+    pub fn new(grounded: bool, desired: Vec3, effective: Vec3) -> Self {
+        Self {
+            grounded,
+            head_bump: desired.y > 0.0 && effective.y < desired.y - 1e-4,
+        }
+    }
+}
+
+/// Input of one movement step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MoveInput {
+    /// Direction to walk in, at most one unit long.
+    pub direction: Vec3,
+    pub max_speed: f32,
+    /// The jump was pressed this step.
+    pub jump: bool,
+}
+
+/// Velocity of the player and the timers around jumping.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Motion {
+    pub velocity: Vec3,
+    /// Seconds since the player last stood on the ground.
+    pub airborne_time: f32,
+    /// Seconds left to use a jump pressed earlier.
+    pub jump_buffer: f32,
+    /// The player jumped since last standing on the ground.
+    pub jumped: bool,
+}
+
+/// Changes `velocity` towards `target`, by at most `acceleration` per second.
+pub fn accelerate(velocity: Vec3, target: Vec3, acceleration: f32, delta: f32) -> Vec3 {
+    velocity + (target - velocity).clamp_length_max(acceleration * delta)
+}
+
+impl Motion {
+    /// Advances the motion by `delta` seconds, after a move that ended with `contacts`.
+    pub fn step(
+        &mut self,
+        settings: &MovementSettings,
+        input: MoveInput,
+        contacts: Contacts,
+        delta: f32,
+    ) {
+        if contacts.grounded {
+            self.airborne_time = 0.0;
+            self.jumped = false;
+            self.velocity.y = self.velocity.y.max(0.0);
+        } else {
+            self.airborne_time += delta;
+        }
+
+        if contacts.head_bump {
+            self.velocity.y = self.velocity.y.min(0.0);
+        }
+
+        if input.jump {
+            self.jump_buffer = settings.jump_buffer;
+        }
+
+        if self.jump_buffer > 0.0 && !self.jumped && self.airborne_time <= settings.coyote_time {
+            self.velocity.y = settings.jump_velocity();
+            self.jump_buffer = 0.0;
+            self.jumped = true;
+        } else {
+            self.jump_buffer = (self.jump_buffer - delta).max(0.0);
+        }
+
+        let on_ground = contacts.grounded && !self.jumped;
+        let acceleration = if !on_ground {
+            settings.air_acceleration
+        } else if input.direction == Vec3::ZERO {
+            settings.friction
+        } else {
+            settings.ground_acceleration
+        };
+
+        let horizontal = accelerate(
+            self.velocity * Vec3::new(1.0, 0.0, 1.0),
+            input.direction * input.max_speed,
+            acceleration,
+            delta,
+        );
+
+        self.velocity = Vec3::new(
+            horizontal.x,
+            (self.velocity.y - settings.gravity * delta).max(-settings.max_fall_speed),
+            horizontal.z,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: f32 = 1.0 / 60.0;
+
+    const GROUND: Contacts = Contacts {
+        grounded: true,
+        head_bump: false,
+    };
+
+    const AIR: Contacts = Contacts {
+        grounded: false,
+        head_bump: false,
+    };
+
+    fn walk(direction: Vec3) -> MoveInput {
+        MoveInput {
+            direction,
+            max_speed: 10.0,
+            jump: false,
+        }
+    }
+
+    fn jump() -> MoveInput {
+        MoveInput {
+            jump: true,
+            ..walk(Vec3::ZERO)
+        }
+    }
+
+    #[test]
+    fn acceleration_is_limited() {
+        let velocity = accelerate(Vec3::ZERO, Vec3::X * 10.0, 60.0, 0.1);
+        assert_eq!(velocity, Vec3::X * 6.0);
+
+        let velocity = accelerate(velocity, Vec3::X * 10.0, 60.0, 0.1);
+        assert_eq!(velocity, Vec3::X * 10.0);
+
+        // stopping is limited the same way
+        let velocity = accelerate(velocity, Vec3::ZERO, 60.0, 0.1);
+        assert_eq!(velocity, Vec3::X * 4.0);
+    }
+
+    #[test]
+    fn walking_speeds_up_and_stops_with_friction() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion::default();
+
+        motion.step(&settings, walk(Vec3::X), GROUND, 0.1);
+        assert!((motion.velocity.x - 8.0).abs() < 1e-4);
+
+        motion.step(&settings, walk(Vec3::X), GROUND, 0.1);
+        assert!((motion.velocity.x - 10.0).abs() < 1e-4);
+
+        motion.step(&settings, walk(Vec3::ZERO), GROUND, 0.1);
+        assert!((motion.velocity.x - 4.0).abs() < 1e-4);
+
+        motion.step(&settings, walk(Vec3::ZERO), GROUND, 0.1);
+        assert_eq!(motion.velocity.x, 0.0);
+    }
+
+    #[test]
+    fn air_control_is_weaker_and_keeps_momentum() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion {
+            velocity: Vec3::X * 10.0,
+            ..default()
+        };
+
+        motion.step(&settings, walk(Vec3::ZERO), AIR, 0.1);
+        assert!((motion.velocity.x - 8.0).abs() < 1e-4);
+        assert!((motion.velocity.y + 2.0).abs() < 1e-4);
+
+        motion.step(&settings, walk(-Vec3::X), AIR, 0.1);
+        assert!((motion.velocity.x - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn falling_is_capped() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion::default();
+
+        for _ in 0..600 {
+            motion.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+
+        assert_eq!(motion.velocity.y, -settings.max_fall_speed);
+    }
+
+    #[test]
+    fn jumps_reach_the_jump_height() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion::default();
+
+        motion.step(&settings, jump(), GROUND, DELTA);
+        assert!(motion.jumped);
+
+        let mut height = 0.0_f32;
+        let mut peak = 0.0_f32;
+
+        while motion.velocity.y > 0.0 {
+            height += motion.velocity.y * DELTA;
+            peak = peak.max(height);
+            motion.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+
+        assert!((peak - settings.jump_height).abs() < 0.2, "peak {}", peak);
+    }
+
+    #[test]
+    fn coyote_time_allows_late_jumps() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion::default();
+
+        motion.step(&settings, walk(Vec3::ZERO), GROUND, DELTA);
+
+        // walked off a ledge a few frames ago
+        for _ in 0..5 {
+            motion.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+        motion.step(&settings, jump(), AIR, DELTA);
+        assert_eq!(
+            motion.velocity.y,
+            settings.jump_velocity() - settings.gravity * DELTA
+        );
+
+        // but not a second time
+        let mut late = Motion::default();
+        for _ in 0..20 {
+            late.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+        late.step(&settings, jump(), AIR, DELTA);
+        assert!(late.velocity.y < 0.0);
+        assert!(!late.jumped);
+    }
+
+    #[test]
+    fn jumps_pressed_before_landing_are_buffered() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion {
+            airborne_time: 1.0,
+            ..default()
+        };
+
+        motion.step(&settings, jump(), AIR, DELTA);
+        assert!(!motion.jumped);
+
+        for _ in 0..3 {
+            motion.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+        motion.step(&settings, walk(Vec3::ZERO), GROUND, DELTA);
+        assert!(motion.jumped);
+        assert!(motion.velocity.y > 0.0);
+
+        // too early a press is forgotten
+        let mut motion = Motion {
+            airborne_time: 1.0,
+            ..default()
+        };
+        motion.step(&settings, jump(), AIR, DELTA);
+        for _ in 0..30 {
+            motion.step(&settings, walk(Vec3::ZERO), AIR, DELTA);
+        }
+        motion.step(&settings, walk(Vec3::ZERO), GROUND, DELTA);
+        assert!(!motion.jumped);
+    }
+
+    #[test]
+    fn head_bumps_stop_the_jump() {
+        let settings = MovementSettings::default();
+        let mut motion = Motion::default();
+
+        motion.step(&settings, jump(), GROUND, DELTA);
+
+        let contacts = Contacts::new(false, Vec3::Y * 0.2, Vec3::Y * 0.05);
+        assert!(contacts.head_bump);
+
+        motion.step(&settings, walk(Vec3::ZERO), contacts, DELTA);
+        assert!(motion.velocity.y < 0.0);
+
+        // falling is never a head bump
+        assert!(!Contacts::new(false, -Vec3::Y, Vec3::ZERO).head_bump);
+    }
+}
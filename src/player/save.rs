@@ -0,0 +1,260 @@
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{inventory::Inventory, movement::Motion, Mode, Player, PlayerCamera};
+use crate::voxel::{self, storage};
+
+/// File of the world save folder the player is kept in.
+const PLAYER_FILE: &str = "player.json";
+
+/// Seconds between two saves of the player.
+const SAVE_INTERVAL: f32 = 30.0;
+
+/// Player state stored in the world save folder. Fields missing from older saves take
+/// their default, so more can be added.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct PlayerSave {
+    position: Vec3,
+    velocity: Vec3,
+    flying: bool,
+    camera_rotation: Vec2,
+    camera_distance: f32,
+    mode: Mode,
+    /// Where the camera is, away from the player in spectator mode.
+    camera: Transform,
+    inventory: Inventory,
+}
+
+impl Default for PlayerSave {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            flying: false,
+            camera_rotation: Vec2::ZERO,
+            camera_distance: 15.0,
+            mode: Mode::Free,
+            camera: Transform::IDENTITY,
+            inventory: Inventory::default(),
+        }
+    }
+}
+
+/// Save read when the world was opened, applied once the ground under it is loaded.
+#[derive(Resource)]
+pub(super) struct RestoredPlayer(PlayerSave);
+
+pub(super) fn load_player(mut commands: Commands, world: Res<voxel::World>) {
+    let Some(dir) = &world.save_dir else {
+        return;
+    };
+
+    match storage::read_json::<PlayerSave>(dir, PLAYER_FILE) {
+        Ok(Some(save)) => commands.insert_resource(RestoredPlayer(save)),
+        Ok(None) => {}
+        Err(error) => warn!("could not load the player: {}", error),
+    }
+}
+
+/// Puts the player back where it was saved, instead of at the spawn point. Until the
+/// ground there is loaded, the player and camera are held at the save so the chunks around
+/// it stream in.
+pub(super) fn restore_player(
+    mut commands: Commands,
+    restored: Option<Res<RestoredPlayer>>,
+    world: Res<voxel::World>,
+    mut player_query: Query<(&mut Transform, &mut Player, &mut Inventory), Without<PlayerCamera>>,
+    mut camera_query: Query<(&mut Transform, &mut PlayerCamera)>,
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Some(restored) = restored else {
+        return;
+    };
+
+    let save = &restored.0;
+    let position = save.position.floor().as_ivec3();
+    let loaded = world.has_collider(position.x, position.z);
+
+    for (mut transform, mut player, mut inventory) in player_query.iter_mut() {
+        transform.translation = save.position;
+        player.spawned = loaded;
+
+        if !loaded {
+            continue;
+        }
+
+        *inventory = save.inventory.clone();
+        inventory.sanitize();
+        player.motion = Motion {
+            velocity: save.velocity,
+            ..default()
+        };
+        player.flying = save.flying;
+        player.camera_rotation = save.camera_rotation;
+        player.camera_distance = save.camera_distance;
+        player.camera_current_distance = save.camera_distance;
+    }
+
+    for (mut transform, mut camera) in camera_query.iter_mut() {
+        *transform = save.camera;
+
+        if loaded {
+            camera.mode = save.mode;
+        }
+    }
+
+    if !loaded {
+        return;
+    }
+
+    if save.mode != Mode::Free {
+        for mut window in primary_window.iter_mut() {
+            window.cursor.grab_mode = CursorGrabMode::Locked;
+            window.cursor.visible = false;
+        }
+    }
+
+    commands.remove_resource::<RestoredPlayer>();
+}
+
+/// Writes the player to the world save folder every `SAVE_INTERVAL` seconds and when the
+/// app exits.
+pub(super) fn save_player(
+    world: Res<voxel::World>,
+    player_query: Query<(&Transform, &Player, &Inventory), Without<PlayerCamera>>,
+    camera_query: Query<(&Transform, &PlayerCamera)>,
+    mut exit_events: EventReader<AppExit>,
+    time: Res<Time>,
+    mut elapsed: Local<f32>,
+) {
+    *elapsed += time.delta_seconds();
+
+    let exiting = exit_events.read().count() > 0;
+
+    if *elapsed < SAVE_INTERVAL && !exiting {
+        return;
+    }
+
+    *elapsed = 0.0;
+
+    let Some(dir) = &world.save_dir else {
+        return;
+    };
+
+    let (Ok((transform, player, inventory)), Ok((camera_transform, camera))) =
+        (player_query.get_single(), camera_query.get_single())
+    else {
+        return;
+    };
+
+    // until then the player is only waiting above the spawn point
+    if !player.spawned {
+        return;
+    }
+
+    let save = PlayerSave {
+        position: transform.translation,
+        velocity: player.motion.velocity,
+        flying: player.flying,
+        camera_rotation: player.camera_rotation,
+        camera_distance: player.camera_distance,
+        mode: camera.mode,
+        camera: *camera_transform,
+        inventory: inventory.clone(),
+    };
+
+    if let Err(error) = storage::write_json(dir, PLAYER_FILE, &save) {
+        warn!("could not save the player: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier3d::geometry::Collider;
+
+    use super::*;
+    use crate::player::{spawn_player, SpawnPoint};
+    use crate::voxel::{Chunk, CHUNK_SIZE};
+
+    #[test]
+    fn saves_round_trip_and_fill_missing_fields() {
+        let save = PlayerSave {
+            position: Vec3::new(10.5, 40.0, -3.0),
+            velocity: Vec3::Y * -2.0,
+            flying: true,
+            camera_rotation: Vec2::new(0.3, 1.2),
+            camera_distance: 8.0,
+            mode: Mode::FirstPerson,
+            camera: Transform::from_xyz(1.0, 2.0, 3.0),
+            inventory: Inventory {
+                selected: 4,
+                ..default()
+            },
+        };
+
+        let json = serde_json::to_string(&save).unwrap();
+        assert_eq!(serde_json::from_str::<PlayerSave>(&json).unwrap(), save);
+
+        let old: PlayerSave = serde_json::from_str(r#"{"position":[1.0,2.0,3.0]}"#).unwrap();
+        assert_eq!(old.position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(old.mode, Mode::Free);
+        assert_eq!(old.camera_distance, 15.0);
+    }
+
+    #[test]
+    fn far_saves_are_restored_instead_of_the_spawn() {
+        let far = Vec3::new(1000.5, 20.0, -700.5);
+        // the chunk holding it, far from the spawn chunk at the origin
+        let chunk = IVec3::new(1000 / CHUNK_SIZE as i32, 0, -701 / CHUNK_SIZE as i32 - 1);
+
+        let mut world = voxel::World::new();
+        world.insert_loaded_chunk(Chunk::new(IVec3::ZERO)).collider = Some(Collider::ball(1.0));
+        world.insert_loaded_chunk(Chunk::new(chunk));
+
+        let mut app = App::new();
+        app.insert_resource(world)
+            .init_resource::<SpawnPoint>()
+            .insert_resource(RestoredPlayer(PlayerSave {
+                position: far,
+                mode: Mode::Orbit,
+                ..default()
+            }))
+            .add_systems(Update, (restore_player, spawn_player).chain());
+
+        let spawn = app.world.resource::<SpawnPoint>().held_position();
+        let player = app
+            .world
+            .spawn((
+                Transform::from_translation(spawn),
+                Player::default(),
+                Inventory::default(),
+            ))
+            .id();
+        app.world
+            .spawn((Transform::IDENTITY, PlayerCamera { mode: Mode::Free }));
+
+        // the spawn is ready but the save is not, so the player waits at the save
+        app.update();
+        let transform = app.world.get::<Transform>(player).unwrap();
+        assert_eq!(transform.translation, far);
+        assert!(!app.world.get::<Player>(player).unwrap().spawned);
+
+        app.world
+            .resource_mut::<voxel::World>()
+            .chunks
+            .get_mut(&chunk)
+            .unwrap()
+            .collider = Some(Collider::ball(1.0));
+
+        app.update();
+        let transform = app.world.get::<Transform>(player).unwrap();
+        assert_eq!(transform.translation, far);
+        assert!(app.world.get::<Player>(player).unwrap().spawned);
+        assert!(!app.world.contains_resource::<RestoredPlayer>());
+    }
+}
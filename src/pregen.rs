@@ -0,0 +1,152 @@
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use crate::cli::PregenOptions;
+use crate::voxel::{self, storage};
+
+/// Generates and saves every chunk within the radius, nearest to the center first. Chunks
+/// already saved are skipped, so an interrupted run resumes where it stopped.
+pub fn run(options: &PregenOptions) -> Result<(), String> {
+    let out = &options.out;
+
+    fs::create_dir_all(out).map_err(|error| format!("{}: {}", out.display(), error))?;
+
+    let saved = storage::read_info(out).map_err(|error| format!("world.json: {}", error))?;
+
+    let info = match (saved, options.seed) {
+        (Some(saved), Some(seed)) if saved.seed != seed => {
+            return Err(format!(
+                "{} was generated with seed {}, not {}",
+                out.display(),
+                saved.seed,
+                seed
+            ));
+        }
+        (Some(saved), _) => saved,
+        (None, seed) => storage::WorldInfo {
+            seed: seed.unwrap_or(voxel::World::default().seed),
+            meshing: options.meshing,
+            sky: None,
+        },
+    };
+
+    storage::write_info(out, &info).map_err(|error| format!("world.json: {}", error))?;
+
+    let mut positions = vec![];
+    for x in -options.radius..=options.radius {
+        for z in -options.radius..=options.radius {
+            positions.push(IVec2::new(x, z));
+        }
+    }
+    positions.sort_by_key(|offset| offset.length_squared());
+
+    let positions: Vec<IVec3> = positions
+        .into_iter()
+        .map(|offset| {
+            let column = options.center + offset;
+            IVec3::new(column.x, 0, column.y)
+        })
+        .collect();
+
+    let total = positions.len();
+    let pending: Vec<IVec3> = positions
+        .into_iter()
+        .filter(|position| !storage::chunk_path(out, *position).exists())
+        .collect();
+    let skipped = total - pending.len();
+
+    println!(
+        "pregen: {} chunks around {} with seed {}, {} already saved",
+        total, options.center, info.seed, skipped
+    );
+
+    let threads = options
+        .threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .max(1);
+
+    let pending = Arc::new(pending);
+    let next = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicBool::new(false));
+    let error = Arc::new(Mutex::new(None));
+
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let (pending, next, done, failed, error) = (
+                pending.clone(),
+                next.clone(),
+                done.clone(),
+                failed.clone(),
+                error.clone(),
+            );
+            let (out, info) = (out.clone(), info.clone());
+
+            thread::spawn(move || {
+                while !failed.load(Ordering::Relaxed) {
+                    let Some(position) = pending.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+
+                    let chunk =
+                        voxel::generate_chunk_data(position.x, position.z, info.meshing, info.seed);
+
+                    if let Err(e) = storage::save_chunk(&out, &chunk) {
+                        *error.lock().unwrap() = Some(format!("chunk {}: {}", position, e));
+                        failed.store(true, Ordering::Relaxed);
+                        break;
+                    }
+
+                    done.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+
+    let start = Instant::now();
+    let mut last_report = start;
+
+    while workers.iter().any(|worker| !worker.is_finished()) {
+        thread::sleep(Duration::from_millis(100));
+
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            report(done.load(Ordering::Relaxed), pending.len(), start.elapsed());
+            last_report = Instant::now();
+        }
+    }
+
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| "a pregen worker panicked".to_string())?;
+    }
+
+    if let Some(error) = error.lock().unwrap().take() {
+        return Err(error);
+    }
+
+    report(done.load(Ordering::Relaxed), pending.len(), start.elapsed());
+    println!("pregen: done, {} chunks saved to {}", total, out.display());
+
+    Ok(())
+}
+
+fn report(done: usize, pending: usize, elapsed: Duration) {
+    let rate = done as f64 / elapsed.as_secs_f64().max(1e-3);
+    let eta = (pending - done) as f64 / rate.max(1e-3);
+
+    println!(
+        "pregen: {}/{} chunks, {:.1} chunks/s, eta {:.0}s",
+        done, pending, rate, eta
+    );
+}
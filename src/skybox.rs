@@ -0,0 +1,156 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    transform::TransformSystem,
+};
+
+use crate::player::Underwater;
+
+mod cubemap;
+mod material;
+mod time_of_day;
+
+pub use cubemap::{Cubemap, CubemapSource};
+pub use material::SkyboxMaterial;
+pub use time_of_day::TimeOfDay;
+
+/// Radius of the sky dome, inside the camera's far plane.
+const DOME_RADIUS: f32 = 800.0;
+
+/// Marks the dome the sky is drawn on.
+#[derive(Component)]
+pub struct SkyDome;
+
+/// Marks the `DirectionalLight` lighting the scene by day.
+#[derive(Component)]
+pub struct Sun;
+
+/// Marks the `DirectionalLight` lighting the scene by night.
+#[derive(Component)]
+pub struct Moon;
+
+/// Draws a procedural sky around the camera, with its sun and moon following the `Sun`
+/// and `Moon` lights and the camera fog fading into the horizon. The `TimeOfDay` moves
+/// them and colors the sky. A `Cubemap` with a source replaces the procedural sky.
+#[derive(Default)]
+pub struct SkyboxPlugin;
+
+impl Plugin for SkyboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<SkyboxMaterial>::default());
+        app.init_resource::<TimeOfDay>();
+        app.init_resource::<Cubemap>();
+        app.add_systems(Startup, setup);
+        app.add_systems(
+            Update,
+            (
+                time_of_day::advance_time,
+                time_of_day::update_day_night,
+                update_sun,
+                match_fog,
+            )
+                .chain(),
+        );
+        app.add_systems(Update, cubemap::update_cubemap);
+        app.add_systems(
+            PostUpdate,
+            follow_camera.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SkyboxMaterial>>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: DOME_RADIUS,
+                sectors: 32,
+                stacks: 16,
+            })),
+            material: materials.add(SkyboxMaterial::default()),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        SkyDome,
+    ));
+
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: Color::rgb(0.6, 0.7, 1.0),
+                illuminance: 0.0,
+                ..default()
+            },
+            ..default()
+        },
+        Moon,
+    ));
+}
+
+fn follow_camera(
+    camera_query: Query<&Transform, (With<Camera3d>, Without<SkyDome>)>,
+    mut dome_query: Query<&mut Transform, With<SkyDome>>,
+) {
+    let Some(camera) = camera_query.iter().next() else {
+        return;
+    };
+
+    for mut transform in dome_query.iter_mut() {
+        transform.translation = camera.translation;
+    }
+}
+
+/// Points the sun and moon discs against the direction their lights shine in.
+fn update_sun(
+    sun_query: Query<Ref<GlobalTransform>, (With<DirectionalLight>, With<Sun>)>,
+    moon_query: Query<Ref<GlobalTransform>, (With<DirectionalLight>, With<Moon>)>,
+    dome_query: Query<&Handle<SkyboxMaterial>, With<SkyDome>>,
+    mut materials: ResMut<Assets<SkyboxMaterial>>,
+) {
+    let sun = sun_query.iter().next().filter(|sun| sun.is_changed());
+    let moon = moon_query.iter().next().filter(|moon| moon.is_changed());
+
+    if sun.is_none() && moon.is_none() {
+        return;
+    }
+
+    for handle in dome_query.iter() {
+        let Some(material) = materials.get_mut(handle) else {
+            continue;
+        };
+
+        if let Some(sun) = &sun {
+            material.sun_direction = sun.back();
+        }
+
+        if let Some(moon) = &moon {
+            material.moon_direction = moon.back();
+        }
+    }
+}
+
+fn match_fog(
+    dome_query: Query<&Handle<SkyboxMaterial>, With<SkyDome>>,
+    materials: Res<Assets<SkyboxMaterial>>,
+    // the underwater fog keeps its own color
+    mut fog_query: Query<&mut FogSettings, Without<Underwater>>,
+) {
+    let Some(material) = dome_query
+        .iter()
+        .next()
+        .and_then(|handle| materials.get(handle))
+    else {
+        return;
+    };
+
+    for mut fog in fog_query.iter_mut() {
+        if fog.color != material.horizon_color {
+            fog.color = material.horizon_color;
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::{
+    asset::LoadState,
+    core_pipeline::Skybox,
+    prelude::*,
+    render::render_resource::{
+        Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::SkyDome;
+use crate::player::PlayerCamera;
+
+/// Images a cubemap sky is made from, as paths in the assets folder.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CubemapSource {
+    /// One image per face, in +x, -x, +y, -y, +z, -z order.
+    Faces([String; 6]),
+    /// One image with the six faces stacked from top to bottom, in the same order.
+    Stacked(String),
+    /// One panorama image, longitude along its width and latitude along its height.
+    Equirectangular(String),
+}
+
+/// Sky shown behind the `PlayerCamera` instead of the procedural one, when it has a
+/// source.
+#[derive(Resource, Default)]
+pub struct Cubemap {
+    pub source: Option<CubemapSource>,
+    images: Vec<Handle<Image>>,
+    image: Option<Handle<Image>>,
+}
+
+impl Cubemap {
+    pub fn new(source: Option<CubemapSource>) -> Self {
+        Self {
+            source,
+            ..default()
+        }
+    }
+}
+
+/// Direction through the texel at `(u, v)` of a cube face, both from -1 to 1, following
+/// the face order and orientation of cube textures.
+pub fn face_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+    .normalize()
+}
+
+/// Resamples a panorama of 4 byte pixels into six stacked square faces of `size` pixels.
+pub fn equirectangular_to_faces(data: &[u8], width: u32, height: u32, size: u32) -> Vec<u8> {
+    let mut faces = Vec::with_capacity((size * size * 6 * 4) as usize);
+
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let direction = face_direction(face, u, v);
+
+                let longitude = direction.z.atan2(direction.x);
+                let latitude = direction.y.clamp(-1.0, 1.0).asin();
+
+                let px = ((longitude / TAU + 0.5) * width as f32) as u32;
+                let py = ((0.5 - latitude / PI) * height as f32) as u32;
+                let index = ((py.min(height - 1) * width + px.min(width - 1)) * 4) as usize;
+
+                faces.extend_from_slice(&data[index..index + 4]);
+            }
+        }
+    }
+
+    faces
+}
+
+/// Builds the cube texture of a source from its loaded images.
+fn build_cubemap(source: &CubemapSource, images: &[&Image]) -> Result<Image, String> {
+    let mut cubemap = match source {
+        CubemapSource::Stacked(_) => {
+            let mut image = images[0].clone();
+
+            if image.height() != image.width() * 6 {
+                return Err("stacked cubemaps must be six squares high".to_string());
+            }
+
+            image.reinterpret_stacked_2d_as_array(6);
+            image
+        }
+        CubemapSource::Faces(_) => {
+            let first = images[0];
+
+            if first.width() != first.height()
+                || images.iter().any(|image| {
+                    image.size() != first.size()
+                        || image.texture_descriptor.format != first.texture_descriptor.format
+                })
+            {
+                return Err("cubemap faces must be squares of the same size and format".to_string());
+            }
+
+            Image::new(
+                Extent3d {
+                    width: first.width(),
+                    height: first.width(),
+                    depth_or_array_layers: 6,
+                },
+                TextureDimension::D2,
+                images
+                    .iter()
+                    .flat_map(|image| image.data.iter().copied())
+                    .collect(),
+                first.texture_descriptor.format,
+            )
+        }
+        CubemapSource::Equirectangular(_) => {
+            let panorama = images[0];
+            let format = panorama.texture_descriptor.format;
+
+            if !matches!(
+                format,
+                TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm
+            ) {
+                return Err(format!("unsupported panorama format {:?}", format));
+            }
+
+            let size = (panorama.width() / 4).max(1);
+
+            Image::new(
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                TextureDimension::D2,
+                equirectangular_to_faces(&panorama.data, panorama.width(), panorama.height(), size),
+                format,
+            )
+        }
+    };
+
+    cubemap.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+
+    Ok(cubemap)
+}
+
+/// Loads the cubemap images, builds the cube texture once they are all there and shows it
+/// behind the cameras in place of the sky dome.
+pub fn update_cubemap(
+    mut commands: Commands,
+    mut cubemap: ResMut<Cubemap>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    camera_query: Query<Entity, (With<PlayerCamera>, Without<Skybox>)>,
+    mut dome_query: Query<&mut Visibility, With<SkyDome>>,
+) {
+    let Some(source) = cubemap.source.clone() else {
+        return;
+    };
+
+    if let Some(image) = &cubemap.image {
+        for entity in camera_query.iter() {
+            commands.entity(entity).insert(Skybox(image.clone()));
+        }
+
+        return;
+    }
+
+    if cubemap.images.is_empty() {
+        cubemap.images = match &source {
+            CubemapSource::Faces(paths) => {
+                paths.iter().map(|path| asset_server.load(path)).collect()
+            }
+            CubemapSource::Stacked(path) | CubemapSource::Equirectangular(path) => {
+                vec![asset_server.load(path)]
+            }
+        };
+    }
+
+    if cubemap
+        .images
+        .iter()
+        .any(|image| asset_server.get_load_state(image) == Some(LoadState::Failed))
+    {
+        warn!("could not load the cubemap images, keeping the procedural sky");
+        cubemap.source = None;
+        return;
+    }
+
+    let Some(loaded) = cubemap
+        .images
+        .iter()
+        .map(|handle| images.get(handle))
+        .collect::<Option<Vec<&Image>>>()
+    else {
+        return;
+    };
+
+    match build_cubemap(&source, &loaded) {
+        Ok(image) => {
+            cubemap.image = Some(images.add(image));
+            cubemap.images.clear();
+
+            for mut visibility in dome_query.iter_mut() {
+                *visibility = Visibility::Hidden;
+            }
+        }
+        Err(error) => {
+            warn!("invalid cubemap: {}", error);
+            cubemap.source = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_point_along_their_axis() {
+        let axes = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+
+        for (face, axis) in axes.iter().enumerate() {
+            assert_eq!(face_direction(face, 0.0, 0.0), *axis);
+        }
+
+        // the top of the side faces looks up
+        for face in [0, 1, 4, 5] {
+            assert!(face_direction(face, 0.0, -0.9).y > 0.0);
+        }
+    }
+
+    #[test]
+    fn panorama_is_split_into_faces() {
+        // red sky over a blue ground
+        let (width, height) = (16, 8);
+        let data: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                if i / width < height / 2 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                }
+            })
+            .collect();
+
+        let faces = equirectangular_to_faces(&data, width, height, 4);
+        let face = |face: usize| &faces[face * 64..(face + 1) * 64];
+
+        assert_eq!(faces.len(), 4 * 4 * 6 * 4);
+        assert!(face(2).chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+        assert!(face(3).chunks(4).all(|pixel| pixel == [0, 0, 255, 255]));
+
+        // side faces have the horizon across their middle
+        assert_eq!(&face(0)[..4], &[255, 0, 0, 255]);
+        assert_eq!(&face(0)[60..], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn sources_round_trip_as_json() {
+        let source = CubemapSource::Equirectangular("skies/sunset.png".to_string());
+        let json = serde_json::to_string(&source).unwrap();
+
+        assert_eq!(json, r#"{"equirectangular":"skies/sunset.png"}"#);
+        assert_eq!(
+            serde_json::from_str::<CubemapSource>(&json).unwrap(),
+            source
+        );
+    }
+}
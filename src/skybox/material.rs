@@ -0,0 +1,74 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::TypePath,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+    },
+};
+
+/// Gradient sky from the horizon to the zenith with a sun disc, drawn on a dome around the
+/// camera. Colors depend only on the view direction, so the dome can be any size.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct SkyboxMaterial {
+    #[uniform(0)]
+    pub zenith_color: Color,
+    #[uniform(0)]
+    pub horizon_color: Color,
+    #[uniform(0)]
+    pub ground_color: Color,
+    /// Color of the sun disc and glow, its alpha fading them out.
+    #[uniform(0)]
+    pub sun_color: Color,
+    /// Color of the moon disc, its alpha fading it out.
+    #[uniform(0)]
+    pub moon_color: Color,
+    /// Direction towards the sun.
+    #[uniform(0)]
+    pub sun_direction: Vec3,
+    /// Cosine of the angular radius of the sun disc.
+    #[uniform(0)]
+    pub sun_size: f32,
+    /// Direction towards the moon.
+    #[uniform(0)]
+    pub moon_direction: Vec3,
+    /// Cosine of the angular radius of the moon disc.
+    #[uniform(0)]
+    pub moon_size: f32,
+}
+
+impl Default for SkyboxMaterial {
+    fn default() -> Self {
+        Self {
+            zenith_color: Color::rgb(0.24, 0.45, 0.85),
+            horizon_color: Color::rgb(0.7, 0.8, 0.92),
+            ground_color: Color::rgb(0.35, 0.38, 0.42),
+            sun_color: Color::rgb(1.0, 0.95, 0.8),
+            moon_color: Color::rgba(0.8, 0.85, 0.95, 0.0),
+            sun_direction: Vec3::Y,
+            sun_size: 2f32.to_radians().cos(),
+            moon_direction: -Vec3::Y,
+            moon_size: 1.5f32.to_radians().cos(),
+        }
+    }
+}
+
+impl Material for SkyboxMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/skybox_material.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the dome is seen from the inside
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use super::{Moon, SkyDome, SkyboxMaterial, Sun};
+
+/// Hour of the day, from 0 to 24, and how fast it goes by.
+#[derive(Resource)]
+pub struct TimeOfDay {
+    pub hour: f32,
+    /// Real seconds a whole day lasts.
+    pub day_length: f32,
+    pub paused: bool,
+    /// Illuminance of the sun at noon.
+    pub sun_illuminance: f32,
+    /// Illuminance of the moon at midnight.
+    pub moon_illuminance: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            day_length: 20.0 * 60.0,
+            paused: false,
+            sun_illuminance: 20000.0,
+            moon_illuminance: 600.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn advance(&mut self, seconds: f32) {
+        if self.paused || self.day_length <= 0.0 {
+            return;
+        }
+
+        self.hour = (self.hour + seconds * 24.0 / self.day_length).rem_euclid(24.0);
+    }
+
+    /// Direction towards the sun: rising in the east (+x) at 6, highest at 12 and setting
+    /// in the west at 18, on an arc tilted to the south.
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hour - 6.0) / 24.0 * TAU;
+
+        Quat::from_rotation_x(-0.35) * Vec3::new(angle.cos(), angle.sin(), 0.0)
+    }
+
+    /// How much of the day light there is, from 0 at night to 1 once the sun is up.
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.1, 0.2, self.sun_direction().y)
+    }
+
+    /// How close the sun is to the horizon, 1 right on it.
+    pub fn twilight(&self) -> f32 {
+        (1.0 - self.sun_direction().y.abs() / 0.3).max(0.0)
+    }
+}
+
+fn smoothstep(from: f32, to: f32, x: f32) -> f32 {
+    let t = ((x - from) / (to - from)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    let a = Vec4::from(a.as_rgba_f32());
+    let b = Vec4::from(b.as_rgba_f32());
+    let [r, g, b, alpha] = a.lerp(b, t).to_array();
+
+    Color::rgba(r, g, b, alpha)
+}
+
+/// Approximate color of a black body at `kelvin`, from 1000 K to 40000 K.
+pub fn color_temperature(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let g = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.07551485)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+
+    Color::rgb(
+        r.clamp(0.0, 255.0) / 255.0,
+        g.clamp(0.0, 255.0) / 255.0,
+        b.clamp(0.0, 255.0) / 255.0,
+    )
+}
+
+/// Zenith and horizon colors of the sky.
+pub fn sky_colors(time: &TimeOfDay) -> (Color, Color) {
+    let daylight = time.daylight();
+
+    let zenith = mix(
+        Color::rgb(0.01, 0.015, 0.04),
+        Color::rgb(0.24, 0.45, 0.85),
+        daylight,
+    );
+    let horizon = mix(
+        Color::rgb(0.04, 0.05, 0.1),
+        Color::rgb(0.7, 0.8, 0.92),
+        daylight,
+    );
+
+    (
+        zenith,
+        mix(horizon, Color::rgb(0.95, 0.55, 0.3), time.twilight() * 0.7),
+    )
+}
+
+pub fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    // only touch the resource when the time moves, so the sky is not recolored for nothing
+    if !time_of_day.paused {
+        time_of_day.advance(time.delta_seconds());
+    }
+}
+
+/// Lights marked with `T` and not `U`.
+type LightQuery<'w, 's, T, U> =
+    Query<'w, 's, (&'static mut Transform, &'static mut DirectionalLight), (With<T>, Without<U>)>;
+
+/// Moves the sun and moon, sets their light and the ambient light, and colors the sky.
+pub fn update_day_night(
+    time_of_day: Res<TimeOfDay>,
+    mut sun_query: LightQuery<Sun, Moon>,
+    mut moon_query: LightQuery<Moon, Sun>,
+    mut ambient: ResMut<AmbientLight>,
+    dome_query: Query<&Handle<SkyboxMaterial>, With<SkyDome>>,
+    mut materials: ResMut<Assets<SkyboxMaterial>>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+
+    let to_sun = time_of_day.sun_direction();
+    let daylight = time_of_day.daylight();
+    let night = 1.0 - daylight;
+
+    // reddish at sunrise and sunset, white at noon
+    let sun_color = color_temperature(2000.0 + 4500.0 * to_sun.y.max(0.0).sqrt());
+
+    for (mut transform, mut light) in sun_query.iter_mut() {
+        *transform = Transform::IDENTITY.looking_to(-to_sun, Vec3::Y);
+        light.illuminance = time_of_day.sun_illuminance * daylight;
+        light.color = sun_color;
+    }
+
+    for (mut transform, mut light) in moon_query.iter_mut() {
+        *transform = Transform::IDENTITY.looking_to(to_sun, Vec3::Y);
+        light.illuminance = time_of_day.moon_illuminance * night;
+    }
+
+    ambient.color = mix(Color::rgb(0.6, 0.7, 1.0), Color::WHITE, daylight);
+    ambient.brightness = 0.05 + 0.1 * night;
+
+    let (zenith, horizon) = sky_colors(&time_of_day);
+
+    for handle in dome_query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.zenith_color = zenith;
+            material.horizon_color = horizon;
+            material.ground_color = mix(horizon, Color::BLACK, 0.5);
+            material.sun_color = sun_color.with_a(daylight.max(time_of_day.twilight()));
+            material.moon_color = material.moon_color.with_a(night);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: f32) -> TimeOfDay {
+        TimeOfDay { hour, ..default() }
+    }
+
+    #[test]
+    fn time_wraps_and_pauses() {
+        let mut time = TimeOfDay {
+            hour: 23.0,
+            day_length: 24.0,
+            ..default()
+        };
+
+        time.advance(2.0);
+        assert!((time.hour - 1.0).abs() < 1e-4);
+
+        time.paused = true;
+        time.advance(2.0);
+        assert!((time.hour - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sun_rises_and_sets() {
+        assert!(at(6.0).sun_direction().y.abs() < 1e-4);
+        assert!(at(6.0).sun_direction().x > 0.9);
+        assert!(at(12.0).sun_direction().y > 0.9);
+        assert!(at(18.0).sun_direction().x < -0.9);
+        assert!(at(0.0).sun_direction().y < -0.9);
+
+        assert_eq!(at(12.0).daylight(), 1.0);
+        assert_eq!(at(0.0).daylight(), 0.0);
+        assert_eq!(at(12.0).twilight(), 0.0);
+        assert!(at(18.0).twilight() > 0.9);
+    }
+
+    #[test]
+    fn sky_is_darker_at_night() {
+        let (day_zenith, day_horizon) = sky_colors(&at(12.0));
+        let (night_zenith, night_horizon) = sky_colors(&at(0.0));
+
+        assert!(night_zenith.b() < day_zenith.b());
+        assert!(night_horizon.r() < day_horizon.r());
+
+        // sunsets turn the horizon orange
+        let (_, sunset) = sky_colors(&at(18.0));
+        assert!(sunset.r() > sunset.b());
+    }
+
+    #[test]
+    fn color_temperatures() {
+        let warm = color_temperature(2000.0);
+        let daylight = color_temperature(6600.0);
+        let cold = color_temperature(12000.0);
+
+        assert!(warm.r() > warm.b());
+        assert!(daylight.r() > 0.95 && daylight.g() > 0.95 && daylight.b() > 0.95);
+        assert!(cold.b() > cold.r());
+    }
+}
@@ -1,28 +1,56 @@
 use bevy::prelude::*;
 
+pub mod block;
 pub mod body;
 mod chunk;
+pub mod edit;
+pub mod history;
+pub mod schematic;
+pub mod storage;
+mod terrain;
 mod type_map;
 mod world;
 
 pub use chunk::Chunk;
+pub use chunk::MeshingMode;
 pub use chunk::CHUNK_SIZE;
+pub use terrain::generate_chunk_data;
+pub use world::ChunkLoader;
+pub use world::RayHit;
 pub use world::World;
 
 #[derive(Default)]
-pub struct VoxelPlugins;
+pub struct VoxelPlugins {
+    /// Only generate chunk data: no meshes, materials, colliders or debug windows, so the
+    /// plugin runs on top of `MinimalPlugins`.
+    pub headless: bool,
+}
 
 impl Plugin for VoxelPlugins {
     fn build(&self, app: &mut App) {
         app.init_resource::<world::World>();
+        app.init_resource::<edit::EditTools>();
+        app.init_resource::<edit::Selection>();
+        app.init_resource::<schematic::Clipboard>();
         app.add_systems(Startup, world::startup);
 
-        body::build(app);
+        if !self.headless {
+            body::build(app);
+        }
 
+        app.add_systems(Update, world::stream_chunks);
         app.add_systems(Update, world::update);
         app.add_systems(Update, world::unload_chunks);
         app.add_systems(Update, world::load_chunks);
         app.add_systems(Update, world::generate_chunks);
-        app.add_systems(Update, world::debug);
+        app.add_systems(Update, world::update_lods);
+        app.add_systems(Update, world::remesh_chunks);
+
+        if !self.headless {
+            app.add_systems(Update, world::debug);
+            app.add_systems(Update, history::undo_edits);
+            app.add_systems(Update, edit::tools_window);
+            app.add_systems(Update, schematic::preview_paste);
+        }
     }
 }
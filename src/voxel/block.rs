@@ -0,0 +1,67 @@
+/// Properties shared by every block with the same id.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockInfo {
+    pub name: &'static str,
+    /// Vertex color of the block faces.
+    pub color: [f32; 4],
+    /// Blocks the player and gets a collider.
+    pub solid: bool,
+    /// Can be swum through.
+    pub liquid: bool,
+}
+
+pub const AIR: u8 = 0;
+pub const WATER: u8 = 1;
+pub const GRASS: u8 = 2;
+
+/// Every known block, indexed by id.
+pub const BLOCKS: [BlockInfo; 3] = [
+    BlockInfo {
+        name: "Air",
+        color: [0.0, 0.0, 0.0, 0.0],
+        solid: false,
+        liquid: false,
+    },
+    BlockInfo {
+        name: "Water",
+        color: [0.106, 0.192, 0.549, 1.0],
+        solid: false,
+        liquid: true,
+    },
+    BlockInfo {
+        name: "Grass",
+        color: [0.102, 0.631, 0.259, 1.0],
+        solid: true,
+        liquid: false,
+    },
+];
+
+/// Properties of a block, unknown ids behaving like grass.
+pub fn info(block: u8) -> &'static BlockInfo {
+    BLOCKS
+        .get(block as usize)
+        .unwrap_or(&BLOCKS[GRASS as usize])
+}
+
+pub fn is_solid(block: u8) -> bool {
+    info(block).solid
+}
+
+pub fn is_liquid(block: u8) -> bool {
+    info(block).liquid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn water_is_a_liquid_you_can_walk_into() {
+        assert!(is_liquid(WATER) && !is_solid(WATER));
+        assert!(is_solid(GRASS) && !is_liquid(GRASS));
+        assert!(!is_solid(AIR) && !is_liquid(AIR));
+
+        // saved chunks may hold ids this build does not know
+        assert!(is_solid(200));
+    }
+}
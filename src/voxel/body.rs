@@ -1,11 +1,16 @@
 use std::sync::Arc;
 
+pub mod animation;
+pub mod model;
+
 use bevy::{
     prelude::*,
     reflect::TypePath,
-    render::render_resource::{AsBindGroup, ShaderRef},
+    render::render_resource::{AsBindGroup, PrimitiveTopology, ShaderRef},
 };
 
+/// A model made of a tree of boxes. Entities with a `Body` get one child entity per box,
+/// rebuilt whenever the body changes.
 #[derive(Component)]
 pub struct Body {
     body_box: BodyBox,
@@ -19,45 +24,262 @@ impl Default for Body {
     }
 }
 
-#[derive(Default)]
+impl Body {
+    pub fn new(body_box: BodyBox) -> Self {
+        Self { body_box }
+    }
+
+    pub fn root(&self) -> &BodyBox {
+        &self.body_box
+    }
+}
+
+#[derive(Bundle, Default)]
+pub struct BodyBundle {
+    pub body: Body,
+    pub material: Handle<BodyMaterial>,
+    pub spatial: SpatialBundle,
+}
+
+/// One box of a body.
+///
+/// The box turns and scales around its `pivot`, given in the space of its parent's pivot,
+/// and its cuboid of `size` is centred at `position` relative to the pivot. Children hang
+/// from this box's pivot, so they follow its rotation.
+#[derive(Default, Clone)]
 pub struct BodyBox {
-    parent: Option<Arc<BodyBox>>,
+    name: String,
     children: Vec<Arc<BodyBox>>,
 
     position: Vec3,
     rotation: Quat,
     scale: Vec3,
     pivot: Vec3,
+    size: Vec3,
 
+    /// UVs of the six vertices of each face's two triangles, in face order front (-z), back
+    /// (+z), left (-x), right (+x), top (+y), bottom (-y). Empty maps the whole texture on
+    /// every face.
     uv: Vec<[[f32; 2]; 6]>,
 }
 
 impl BodyBox {
     pub fn new() -> Self {
         Self {
-            parent: None,
+            name: String::new(),
             children: Vec::new(),
 
             position: Vec3::ZERO,
             rotation: Quat::IDENTITY,
             scale: Vec3::ONE,
             pivot: Vec3::ZERO,
+            size: Vec3::ONE,
 
             uv: Vec::new(),
         }
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: Vec3) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn with_size(mut self, size: Vec3) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_uv(mut self, uv: Vec<[[f32; 2]; 6]>) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    /// Maps each face to a rectangle of the texture, given as `[min, max]` UV corners.
+    pub fn with_face_rects(self, rects: [[[f32; 2]; 2]; 6]) -> Self {
+        self.with_uv(rects.iter().map(|rect| face_uv(*rect)).collect())
+    }
+
+    pub fn with_child(mut self, child: BodyBox) -> Self {
+        self.children.push(Arc::new(child));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &BodyBox> {
+        self.children.iter().map(|child| child.as_ref())
+    }
+
+    /// Transform of the box's pivot in its parent's pivot space.
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.pivot,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+
+    /// Builds the cuboid of this box alone, in pivot space.
+    pub fn mesh(&self) -> Mesh {
+        // corners go counter-clockwise from the bottom left, seen from outside
+        const FACES: [([[f32; 3]; 4], [f32; 3]); 6] = [
+            // Front
+            (
+                [
+                    [1.0, 0.0, 0.0],
+                    [0.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                ],
+                [0.0, 0.0, -1.0],
+            ),
+            // Back
+            (
+                [
+                    [0.0, 0.0, 1.0],
+                    [1.0, 0.0, 1.0],
+                    [1.0, 1.0, 1.0],
+                    [0.0, 1.0, 1.0],
+                ],
+                [0.0, 0.0, 1.0],
+            ),
+            // Left
+            (
+                [
+                    [0.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0],
+                    [0.0, 1.0, 1.0],
+                    [0.0, 1.0, 0.0],
+                ],
+                [-1.0, 0.0, 0.0],
+            ),
+            // Right
+            (
+                [
+                    [1.0, 0.0, 1.0],
+                    [1.0, 0.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [1.0, 1.0, 1.0],
+                ],
+                [1.0, 0.0, 0.0],
+            ),
+            // Top
+            (
+                [
+                    [0.0, 1.0, 1.0],
+                    [1.0, 1.0, 1.0],
+                    [1.0, 1.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                ],
+                [0.0, 1.0, 0.0],
+            ),
+            // Bottom
+            (
+                [
+                    [0.0, 0.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [1.0, 0.0, 1.0],
+                    [0.0, 0.0, 1.0],
+                ],
+                [0.0, -1.0, 0.0],
+            ),
+        ];
+
+        let min = self.position - self.size / 2.0;
+
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut normals: Vec<[f32; 3]> = vec![];
+        let mut uvs: Vec<[f32; 2]> = vec![];
+
+        for (face, (corners, normal)) in FACES.iter().enumerate() {
+            let uv = self
+                .uv
+                .get(face)
+                .copied()
+                .unwrap_or_else(|| face_uv([[0.0, 0.0], [1.0, 1.0]]));
+
+            for (i, corner) in [0, 1, 2, 0, 2, 3].into_iter().enumerate() {
+                positions.push((min + Vec3::from(corners[corner]) * self.size).to_array());
+                normals.push(*normal);
+                uvs.push(uv[i]);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
+
+/// UVs of a face's two triangles covering the `[min, max]` rectangle, image upright.
+fn face_uv([min, max]: [[f32; 2]; 2]) -> [[f32; 2]; 6] {
+    let corners = [
+        [min[0], max[1]],
+        [max[0], max[1]],
+        [max[0], min[1]],
+        [min[0], min[1]],
+    ];
+
+    [0, 1, 2, 0, 2, 3].map(|i| corners[i])
+}
+
+/// Marks the entity spawned for a box, named after it.
+#[derive(Component, Clone, Debug)]
+pub struct BodyPart {
+    pub name: String,
+    /// Transform of the box when not animated.
+    pub rest: Transform,
+    /// Entity holding the `Body` the box belongs to.
+    pub body: Entity,
 }
 
 #[derive(Asset, TypePath, AsBindGroup, Clone)]
 pub struct BodyMaterial {
     #[uniform(0)]
-    color: Color,
-    alpha_mode: AlphaMode,
+    pub color: Color,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Option<Handle<Image>>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl Default for BodyMaterial {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            texture: None,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
 }
 
 impl Material for BodyMaterial {
     fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material.wgsl".into()
+        "shaders/body_material.wgsl".into()
     }
 
     fn alpha_mode(&self) -> AlphaMode {
@@ -65,6 +287,144 @@ impl Material for BodyMaterial {
     }
 }
 
+fn spawn_box(
+    parent: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    material: &Handle<BodyMaterial>,
+    body: Entity,
+    body_box: &BodyBox,
+) {
+    parent
+        .spawn((
+            MaterialMeshBundle {
+                mesh: meshes.add(body_box.mesh()),
+                material: material.clone(),
+                transform: body_box.transform(),
+                ..default()
+            },
+            BodyPart {
+                name: body_box.name.clone(),
+                rest: body_box.transform(),
+                body,
+            },
+        ))
+        .with_children(|parent| {
+            for child in body_box.children() {
+                spawn_box(parent, meshes, material, body, child);
+            }
+        });
+}
+
+/// Rebuilds the box entities of bodies that were added or changed.
+pub fn spawn_body_parts(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    bodies_query: Query<(Entity, &Body, &Handle<BodyMaterial>), Changed<Body>>,
+) {
+    for (entity, body, material) in bodies_query.iter() {
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                spawn_box(parent, &mut meshes, material, entity, &body.body_box);
+            });
+    }
+}
+
 pub fn build(app: &mut App) {
     app.add_plugins(MaterialPlugin::<BodyMaterial>::default());
+    app.init_asset::<animation::BodyClip>();
+    model::build(app);
+    app.add_systems(
+        Update,
+        (
+            model::apply_body_models,
+            spawn_body_parts,
+            animation::advance_animators,
+            animation::apply_animations,
+        )
+            .chain(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::mesh::VertexAttributeValues;
+
+    fn attribute(
+        mesh: &Mesh,
+        id: impl Into<bevy::render::mesh::MeshVertexAttributeId>,
+    ) -> Vec<Vec3> {
+        match mesh.attribute(id) {
+            Some(VertexAttributeValues::Float32x3(values)) => {
+                values.iter().map(|v| Vec3::from(*v)).collect()
+            }
+            _ => panic!("missing attribute"),
+        }
+    }
+
+    #[test]
+    fn box_mesh_is_a_cuboid_around_its_position() {
+        let body_box = BodyBox::new()
+            .with_position(Vec3::new(0.0, 1.0, 0.0))
+            .with_size(Vec3::new(2.0, 4.0, 1.0));
+
+        let mesh = body_box.mesh();
+        let positions = attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+
+        assert_eq!(positions.len(), 36);
+
+        let min = positions.iter().fold(Vec3::INFINITY, |a, b| a.min(*b));
+        let max = positions.iter().fold(Vec3::NEG_INFINITY, |a, b| a.max(*b));
+        assert_eq!(min, Vec3::new(-1.0, -1.0, -0.5));
+        assert_eq!(max, Vec3::new(1.0, 3.0, 0.5));
+    }
+
+    #[test]
+    fn box_faces_point_outwards() {
+        let mesh = BodyBox::new().with_size(Vec3::splat(2.0)).mesh();
+        let positions = attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+        let normals = attribute(&mesh, Mesh::ATTRIBUTE_NORMAL);
+
+        for (triangle, normal) in positions.chunks(3).zip(normals.chunks(3)) {
+            let winding = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+
+            assert_eq!(winding.normalize(), normal[0]);
+            assert!(triangle[0].dot(normal[0]) > 0.0);
+        }
+    }
+
+    #[test]
+    fn face_rects_set_per_face_uvs() {
+        let mut rects = [[[0.0, 0.0], [1.0, 1.0]]; 6];
+        rects[4] = [[0.25, 0.5], [0.5, 0.75]];
+
+        let mesh = BodyBox::new().with_face_rects(rects).mesh();
+
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("missing uvs");
+        };
+
+        let top = &uvs[24..30];
+        assert!(top
+            .iter()
+            .all(|[u, v]| (0.25..=0.5).contains(u) && (0.5..=0.75).contains(v)));
+        assert!(uvs[..24].iter().any(|uv| *uv == [1.0, 1.0]));
+    }
+
+    #[test]
+    fn transform_applies_around_pivot() {
+        let arm = BodyBox::new()
+            .with_pivot(Vec3::new(0.5, 1.5, 0.0))
+            .with_rotation(Quat::from_rotation_x(0.5))
+            .with_scale(Vec3::splat(2.0));
+
+        let transform = arm.transform();
+
+        assert_eq!(transform.translation, Vec3::new(0.5, 1.5, 0.0));
+        assert_eq!(transform.rotation, Quat::from_rotation_x(0.5));
+        assert_eq!(transform.transform_point(Vec3::ZERO), arm.pivot);
+    }
 }
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, reflect::TypePath};
+
+use super::BodyPart;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Holds the previous value until the keyframe is reached.
+    Step,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A value at a point of a clip, reached from the previous keyframe with `easing`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    pub easing: Easing,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T) -> Self {
+        Self {
+            time,
+            value,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+}
+
+/// Keyframes of one box, relative to its rest pose: rotations apply after the rest
+/// rotation, translations offset the pivot and scales multiply the rest scale.
+#[derive(Clone, Default, Debug)]
+pub struct BoxTrack {
+    pub rotation: Vec<Keyframe<Quat>>,
+    pub translation: Vec<Keyframe<Vec3>>,
+    pub scale: Vec<Keyframe<Vec3>>,
+}
+
+impl BoxTrack {
+    pub fn sample(&self, time: f32) -> BoxPose {
+        BoxPose {
+            rotation: sample_keyframes(&self.rotation, time, Quat::IDENTITY, Quat::slerp),
+            translation: sample_keyframes(&self.translation, time, Vec3::ZERO, Vec3::lerp),
+            scale: sample_keyframes(&self.scale, time, Vec3::ONE, Vec3::lerp),
+        }
+    }
+}
+
+fn sample_keyframes<T: Copy>(
+    keyframes: &[Keyframe<T>],
+    time: f32,
+    default: T,
+    interpolate: impl Fn(T, T, f32) -> T,
+) -> T {
+    let Some(first) = keyframes.first() else {
+        return default;
+    };
+
+    if time <= first.time {
+        return first.value;
+    }
+
+    for pair in keyframes.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+
+        if time < to.time {
+            let t = (time - from.time) / (to.time - from.time);
+            return interpolate(from.value, to.value, to.easing.apply(t));
+        }
+    }
+
+    keyframes.last().unwrap().value
+}
+
+/// Offset of a box from its rest pose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxPose {
+    pub rotation: Quat,
+    pub translation: Vec3,
+    pub scale: Vec3,
+}
+
+impl Default for BoxPose {
+    fn default() -> Self {
+        Self {
+            rotation: Quat::IDENTITY,
+            translation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl BoxPose {
+    pub fn lerp(&self, other: &BoxPose, t: f32) -> BoxPose {
+        BoxPose {
+            rotation: self.rotation.slerp(other.rotation, t),
+            translation: self.translation.lerp(other.translation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    pub fn apply(&self, rest: &Transform) -> Transform {
+        Transform {
+            translation: rest.translation + self.translation,
+            rotation: rest.rotation * self.rotation,
+            scale: rest.scale * self.scale,
+        }
+    }
+}
+
+/// Keyframed tracks for the boxes of a body, looked up by box name.
+#[derive(Asset, TypePath, Clone, Default, Debug)]
+pub struct BodyClip {
+    pub duration: f32,
+    pub looping: bool,
+    pub tracks: HashMap<String, BoxTrack>,
+}
+
+impl BodyClip {
+    pub fn new(duration: f32, looping: bool) -> Self {
+        Self {
+            duration,
+            looping,
+            tracks: HashMap::new(),
+        }
+    }
+
+    pub fn with_track(mut self, name: impl Into<String>, track: BoxTrack) -> Self {
+        self.tracks.insert(name.into(), track);
+        self
+    }
+
+    /// Pose of the named box at `time`, wrapped or clamped to the clip duration.
+    pub fn sample(&self, name: &str, time: f32) -> BoxPose {
+        let time = if self.looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        };
+
+        self.tracks
+            .get(name)
+            .map_or(BoxPose::default(), |track| track.sample(time))
+    }
+
+    /// Legs and arms swinging in opposition, for humanoid bodies.
+    pub fn walk() -> Self {
+        let swing = |angle: f32| {
+            let rotation = |angle: f32| Quat::from_rotation_x(angle);
+
+            BoxTrack {
+                rotation: vec![
+                    Keyframe::new(0.0, rotation(0.0)),
+                    Keyframe::new(0.2, rotation(angle)).with_easing(Easing::EaseOut),
+                    Keyframe::new(0.4, rotation(0.0)).with_easing(Easing::EaseIn),
+                    Keyframe::new(0.6, rotation(-angle)).with_easing(Easing::EaseOut),
+                    Keyframe::new(0.8, rotation(0.0)).with_easing(Easing::EaseIn),
+                ],
+                ..default()
+            }
+        };
+
+        Self::new(0.8, true)
+            .with_track("left_arm", swing(-0.7))
+            .with_track("right_arm", swing(0.7))
+            .with_track("left_leg", swing(0.7))
+            .with_track("right_leg", swing(-0.7))
+    }
+
+    /// Slow breathing sway, for humanoid bodies.
+    pub fn idle() -> Self {
+        let sway = |angle: f32| BoxTrack {
+            rotation: vec![
+                Keyframe::new(0.0, Quat::IDENTITY),
+                Keyframe::new(1.5, Quat::from_rotation_z(angle)).with_easing(Easing::EaseInOut),
+                Keyframe::new(3.0, Quat::IDENTITY).with_easing(Easing::EaseInOut),
+            ],
+            ..default()
+        };
+
+        Self::new(3.0, true)
+            .with_track("left_arm", sway(-0.06))
+            .with_track("right_arm", sway(0.06))
+    }
+}
+
+/// Plays clips on the boxes of a body, cross-fading when the clip changes.
+#[derive(Component, Default)]
+pub struct BodyAnimator {
+    current: Option<(Handle<BodyClip>, f32)>,
+    previous: Option<(Handle<BodyClip>, f32)>,
+    blend: f32,
+    blend_duration: f32,
+    pub speed: f32,
+}
+
+impl BodyAnimator {
+    pub fn new() -> Self {
+        Self {
+            speed: 1.0,
+            ..default()
+        }
+    }
+
+    /// Switches to `clip`, blending from the playing one over `blend_duration` seconds.
+    /// Playing the current clip again does nothing.
+    pub fn play(&mut self, clip: Handle<BodyClip>, blend_duration: f32) {
+        if self.current.as_ref().map(|(handle, _)| handle) == Some(&clip) {
+            return;
+        }
+
+        self.previous = self.current.take();
+        self.current = Some((clip, 0.0));
+        self.blend = 0.0;
+        self.blend_duration = blend_duration;
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        let delta = delta * self.speed;
+
+        for (_, time) in self.current.iter_mut().chain(self.previous.iter_mut()) {
+            *time += delta;
+        }
+
+        self.blend = if self.blend_duration > 0.0 {
+            (self.blend + delta.abs() / self.blend_duration).min(1.0)
+        } else {
+            1.0
+        };
+
+        if self.blend >= 1.0 {
+            self.previous = None;
+        }
+    }
+
+    /// Blended pose of the named box.
+    pub fn sample(&self, clips: &Assets<BodyClip>, name: &str) -> BoxPose {
+        let pose = |playing: &Option<(Handle<BodyClip>, f32)>| {
+            playing
+                .as_ref()
+                .and_then(|(handle, time)| clips.get(handle).map(|clip| clip.sample(name, *time)))
+        };
+
+        match (pose(&self.previous), pose(&self.current)) {
+            (Some(previous), Some(current)) => previous.lerp(&current, self.blend),
+            (None, Some(current)) => current,
+            (Some(previous), None) => previous,
+            (None, None) => BoxPose::default(),
+        }
+    }
+}
+
+pub fn advance_animators(time: Res<Time>, mut animators_query: Query<&mut BodyAnimator>) {
+    for mut animator in animators_query.iter_mut() {
+        animator.advance(time.delta_seconds());
+    }
+}
+
+pub fn apply_animations(
+    clips: Res<Assets<BodyClip>>,
+    animators_query: Query<&BodyAnimator>,
+    mut parts_query: Query<(&BodyPart, &mut Transform)>,
+) {
+    for (part, mut transform) in parts_query.iter_mut() {
+        let Ok(animator) = animators_query.get(part.body) else {
+            continue;
+        };
+
+        *transform = animator.sample(&clips, &part.name).apply(&part.rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_keep_their_endpoints() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn tracks_interpolate_between_keyframes() {
+        let track = BoxTrack {
+            translation: vec![
+                Keyframe::new(1.0, Vec3::ZERO),
+                Keyframe::new(2.0, Vec3::new(4.0, 0.0, 0.0)),
+                Keyframe::new(3.0, Vec3::ZERO).with_easing(Easing::Step),
+            ],
+            ..default()
+        };
+
+        assert_eq!(track.sample(0.0).translation, Vec3::ZERO);
+        assert_eq!(track.sample(1.5).translation, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(track.sample(2.5).translation, Vec3::new(4.0, 0.0, 0.0));
+        assert_eq!(track.sample(5.0).translation, Vec3::ZERO);
+
+        // tracks without keyframes keep the rest pose
+        assert_eq!(track.sample(1.5).rotation, Quat::IDENTITY);
+        assert_eq!(track.sample(1.5).scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn clips_loop_or_clamp() {
+        let track = BoxTrack {
+            translation: vec![Keyframe::new(0.0, Vec3::ZERO), Keyframe::new(1.0, Vec3::Y)],
+            ..default()
+        };
+
+        let looping = BodyClip::new(1.0, true).with_track("arm", track.clone());
+        let once = BodyClip::new(1.0, false).with_track("arm", track);
+
+        assert_eq!(looping.sample("arm", 1.25).translation, Vec3::Y * 0.25);
+        assert_eq!(once.sample("arm", 1.25).translation, Vec3::Y);
+        assert_eq!(once.sample("leg", 0.5), BoxPose::default());
+    }
+
+    #[test]
+    fn poses_apply_on_top_of_rest() {
+        let rest = Transform::from_xyz(0.0, 2.0, 0.0).with_scale(Vec3::splat(2.0));
+        let pose = BoxPose {
+            rotation: Quat::from_rotation_x(0.5),
+            translation: Vec3::X,
+            scale: Vec3::splat(0.5),
+        };
+
+        let transform = pose.apply(&rest);
+
+        assert_eq!(transform.translation, Vec3::new(1.0, 2.0, 0.0));
+        assert_eq!(transform.rotation, Quat::from_rotation_x(0.5));
+        assert_eq!(transform.scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn animator_blends_to_new_clip() {
+        let mut clips = Assets::<BodyClip>::default();
+        let up = clips.add(BodyClip::new(1.0, true).with_track(
+            "arm",
+            BoxTrack {
+                translation: vec![Keyframe::new(0.0, Vec3::Y)],
+                ..default()
+            },
+        ));
+        let down = clips.add(BodyClip::new(1.0, true).with_track(
+            "arm",
+            BoxTrack {
+                translation: vec![Keyframe::new(0.0, -Vec3::Y)],
+                ..default()
+            },
+        ));
+
+        let mut animator = BodyAnimator::new();
+        animator.play(up.clone(), 0.5);
+        animator.advance(0.1);
+        assert_eq!(animator.sample(&clips, "arm").translation, Vec3::Y);
+
+        animator.play(down.clone(), 0.5);
+        animator.advance(0.25);
+        assert!(animator.sample(&clips, "arm").translation.length() < 1e-5);
+
+        animator.advance(0.25);
+        assert_eq!(animator.sample(&clips, "arm").translation, -Vec3::Y);
+
+        // replaying the current clip does not restart the blend
+        animator.play(down, 0.5);
+        animator.advance(0.1);
+        assert_eq!(animator.sample(&clips, "arm").translation, -Vec3::Y);
+        assert!(animator.previous.is_none());
+    }
+}
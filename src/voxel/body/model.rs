@@ -0,0 +1,320 @@
+//! Body models stored as `.body.json` files.
+//!
+//! A model file holds a tree of boxes in texture pixels, Blockbench style:
+//!
+//! ```json
+//! {
+//!     "units_per_block": 16,
+//!     "texture": "textures/player.png",
+//!     "texture_size": [64, 32],
+//!     "root": {
+//!         "name": "body",
+//!         "pivot": [0, -4, 0],
+//!         "rotation": [0, 0, 0],
+//!         "position": [0, 6, 0],
+//!         "size": [8, 12, 4],
+//!         "uv": { "north": [20, 20, 28, 32], "up": [20, 16, 28, 20] },
+//!         "children": []
+//!     }
+//! }
+//! ```
+//!
+//! Each box's `pivot` is relative to its parent's pivot, `position` is the centre of its
+//! cuboid relative to its own pivot and `rotation` is in degrees, applied in X, Y, Z
+//! order. Lengths are divided by `units_per_block` (16 if missing). Faces are named after
+//! the direction they face: north (-z), south (+z), west (-x), east (+x), up and down,
+//! and map to `[left, top, right, bottom]` pixel rectangles of the texture; faces without
+//! one use the whole texture. `texture` is a path in the assets folder and is optional.
+
+use std::{collections::HashMap, fmt};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    reflect::TypePath,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use super::{Body, BodyBox, BodyMaterial};
+
+/// Face names in the order `BodyBox` stores their UVs.
+const FACE_NAMES: [&str; 6] = ["north", "south", "west", "east", "up", "down"];
+
+#[derive(Deserialize)]
+struct ModelFile {
+    #[serde(default = "default_units_per_block")]
+    units_per_block: f32,
+    texture: Option<String>,
+    #[serde(default = "default_texture_size")]
+    texture_size: [f32; 2],
+    root: BoxFile,
+}
+
+fn default_units_per_block() -> f32 {
+    16.0
+}
+
+fn default_texture_size() -> [f32; 2] {
+    [16.0, 16.0]
+}
+
+#[derive(Deserialize)]
+struct BoxFile {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pivot: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: [f32; 3],
+    #[serde(default)]
+    position: [f32; 3],
+    size: [f32; 3],
+    #[serde(default)]
+    uv: HashMap<String, [f32; 4]>,
+    #[serde(default)]
+    children: Vec<BoxFile>,
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl BoxFile {
+    fn build(&self, model: &ModelFile) -> Result<BodyBox, String> {
+        if let Some(face) = self
+            .uv
+            .keys()
+            .find(|face| !FACE_NAMES.contains(&face.as_str()))
+        {
+            return Err(format!("box '{}' has unknown face '{}'", self.name, face));
+        }
+
+        let units = model.units_per_block;
+        let [width, height] = model.texture_size;
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+
+        let mut body_box = BodyBox::new()
+            .with_name(self.name.clone())
+            .with_pivot(Vec3::from(self.pivot) / units)
+            .with_rotation(Quat::from_euler(EulerRot::XYZ, x, y, z))
+            .with_scale(Vec3::from(self.scale))
+            .with_position(Vec3::from(self.position) / units)
+            .with_size(Vec3::from(self.size) / units);
+
+        if !self.uv.is_empty() {
+            body_box = body_box.with_face_rects(FACE_NAMES.map(|face| {
+                self.uv.get(face).map_or([[0.0, 0.0], [1.0, 1.0]], |rect| {
+                    [
+                        [rect[0] / width, rect[1] / height],
+                        [rect[2] / width, rect[3] / height],
+                    ]
+                })
+            }));
+        }
+
+        for child in self.children.iter() {
+            body_box = body_box.with_child(child.build(model)?);
+        }
+
+        Ok(body_box)
+    }
+}
+
+/// A box tree loaded from a `.body.json` file, with the texture it is painted with.
+#[derive(Asset, TypePath, Clone)]
+pub struct BodyModel {
+    pub root: BodyBox,
+    pub texture: Option<Handle<Image>>,
+}
+
+#[derive(Debug)]
+pub enum BodyModelError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for BodyModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyModelError::Io(error) => write!(f, "could not read body model: {}", error),
+            BodyModelError::Json(error) => write!(f, "invalid body model: {}", error),
+            BodyModelError::Invalid(error) => write!(f, "invalid body model: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for BodyModelError {}
+
+impl From<std::io::Error> for BodyModelError {
+    fn from(error: std::io::Error) -> Self {
+        BodyModelError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for BodyModelError {
+    fn from(error: serde_json::Error) -> Self {
+        BodyModelError::Json(error)
+    }
+}
+
+/// Parses a model file into its box tree and texture path.
+pub fn parse_model(bytes: &[u8]) -> Result<(BodyBox, Option<String>), BodyModelError> {
+    let model: ModelFile = serde_json::from_slice(bytes)?;
+
+    if model.units_per_block <= 0.0 || model.texture_size.iter().any(|size| *size <= 0.0) {
+        return Err(BodyModelError::Invalid(
+            "units_per_block and texture_size must be positive".to_string(),
+        ));
+    }
+
+    let root = model.root.build(&model).map_err(BodyModelError::Invalid)?;
+
+    Ok((root, model.texture.clone()))
+}
+
+#[derive(Default)]
+pub struct BodyModelLoader;
+
+impl AssetLoader for BodyModelLoader {
+    type Asset = BodyModel;
+    type Settings = ();
+    type Error = BodyModelError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<BodyModel, BodyModelError>> {
+        Box::pin(async move {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes).await?;
+
+            let (root, texture) = parse_model(&bytes)?;
+
+            Ok(BodyModel {
+                root,
+                texture: texture.map(|path| load_context.load(path)),
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["body.json"]
+    }
+}
+
+/// Rebuilds the bodies of entities holding a model handle when the model is loaded or
+/// changes on disk, and paints their material with the model's texture.
+pub fn apply_body_models(
+    mut events: EventReader<AssetEvent<BodyModel>>,
+    models: Res<Assets<BodyModel>>,
+    mut materials: ResMut<Assets<BodyMaterial>>,
+    mut bodies_query: Query<(Ref<Handle<BodyModel>>, &mut Body, &Handle<BodyMaterial>)>,
+) {
+    let changed: Vec<AssetId<BodyModel>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (handle, mut body, material) in bodies_query.iter_mut() {
+        // models loaded before the entity was spawned send no more events
+        if !handle.is_added() && !changed.contains(&handle.id()) {
+            continue;
+        }
+
+        let Some(model) = models.get(handle.as_ref()) else {
+            continue;
+        };
+
+        *body = Body::new(model.root.clone());
+
+        if let (Some(texture), Some(material)) = (&model.texture, materials.get_mut(material)) {
+            material.texture = Some(texture.clone());
+        }
+    }
+}
+
+pub fn build(app: &mut App) {
+    app.init_asset::<BodyModel>();
+    app.init_asset_loader::<BodyModelLoader>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYER: &[u8] = include_bytes!("../../../assets/models/player.body.json");
+
+    #[test]
+    fn player_model_loads() {
+        let (root, texture) = parse_model(PLAYER).unwrap();
+
+        assert_eq!(root.name(), "body");
+        assert_eq!(texture, None);
+
+        let names: Vec<&str> = root.children().map(|child| child.name()).collect();
+        assert_eq!(
+            names,
+            ["head", "left_arm", "right_arm", "left_leg", "right_leg"]
+        );
+
+        // pixels are converted to blocks
+        assert_eq!(root.size, Vec3::new(0.5, 0.75, 0.25));
+        assert_eq!(root.transform().translation, Vec3::new(0.0, -0.25, 0.0));
+        assert_eq!(root.uv.len(), 6);
+        assert_eq!(root.uv[0][5], [20.0 / 64.0, 20.0 / 32.0]);
+    }
+
+    #[test]
+    fn defaults_and_rotations() {
+        let (root, _) = parse_model(
+            br#"{
+                "units_per_block": 1,
+                "root": {
+                    "size": [1, 2, 3],
+                    "rotation": [0, 90, 0],
+                    "uv": { "up": [0, 0, 8, 8] },
+                    "children": [{ "name": "child", "size": [1, 1, 1] }]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(root.size, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(root.scale, Vec3::ONE);
+        assert!(root
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_y(90f32.to_radians()), 1e-6));
+
+        // faces without a rectangle keep the whole texture
+        assert_eq!(root.uv[0][5], [0.0, 0.0]);
+        assert_eq!(root.uv[4][2], [0.5, 0.0]);
+
+        assert_eq!(root.children().next().unwrap().name(), "child");
+        assert!(root.children().next().unwrap().uv.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_models() {
+        assert!(matches!(
+            parse_model(b"{ \"root\": {} }"),
+            Err(BodyModelError::Json(_))
+        ));
+        assert!(matches!(
+            parse_model(br#"{ "root": { "size": [1, 1, 1], "uv": { "front": [0, 0, 1, 1] } } }"#),
+            Err(BodyModelError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_model(br#"{ "units_per_block": 0, "root": { "size": [1, 1, 1] } }"#),
+            Err(BodyModelError::Invalid(_))
+        ));
+    }
+}
@@ -7,16 +7,37 @@ use bevy::{
 };
 use bevy_egui::egui::mutex::RwLock;
 use bevy_rapier3d::geometry::Collider;
+use serde::{Deserialize, Serialize};
 
+use super::block;
 use super::world::ChunkState;
 
 pub const CHUNK_SIZE: usize = 64;
 
+/// Density samples per axis: one per block corner, plus one past the far border so the
+/// smooth mesher can stitch the chunk to its neighbours.
+pub const DENSITY_SIZE: usize = CHUNK_SIZE + 2;
+
+const COLOR_WATER: [f32; 4] = block::BLOCKS[block::WATER as usize].color;
+const COLOR_GRASS: [f32; 4] = block::BLOCKS[block::GRASS as usize].color;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshingMode {
+    /// One cube per block.
+    #[default]
+    Blocky,
+    /// Surface nets over the chunk density field.
+    Smooth,
+}
+
 #[derive(Component)]
 pub struct Chunk {
     pub updated: bool,
     pub position: IVec3,
     pub blocks: Arc<RwLock<Vec<u8>>>,
+    /// Signed density sampled at block corners, positive inside the terrain. Only filled
+    /// for chunks meshed with [`MeshingMode::Smooth`].
+    pub density: Arc<RwLock<Vec<f32>>>,
 }
 
 impl Chunk {
@@ -24,10 +45,17 @@ impl Chunk {
         Self {
             position,
             blocks: Arc::new(RwLock::new(vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE])),
+            density: Arc::new(RwLock::new(Vec::new())),
             updated: false,
         }
     }
 
+    pub fn with_density(position: IVec3) -> Self {
+        let chunk = Self::new(position);
+        *chunk.density.as_ref().write() = vec![-1.0; DENSITY_SIZE * DENSITY_SIZE * DENSITY_SIZE];
+        chunk
+    }
+
     pub fn get_world_position(&self) -> Vec3 {
         Vec3::new(
             self.position.x as f32 * CHUNK_SIZE as f32,
@@ -45,6 +73,11 @@ impl Chunk {
         let mut blocks = self.blocks.as_ref().write();
         blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE] = block;
     }
+
+    pub fn set_density(&mut self, x: usize, y: usize, z: usize, density: f32) {
+        let mut samples = self.density.as_ref().write();
+        samples[x + y * DENSITY_SIZE + z * DENSITY_SIZE * DENSITY_SIZE] = density;
+    }
 }
 
 impl Clone for Chunk {
@@ -52,14 +85,114 @@ impl Clone for Chunk {
         Self {
             position: self.position,
             blocks: self.blocks.clone(),
+            density: self.density.clone(),
             updated: self.updated,
         }
     }
 }
 
-pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    let mut colliders: Vec<(Vec3, Quat, Collider)> = vec![];
+/// Collision shape of a chunk, kept as plain data until it is handed to the physics engine.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChunkCollider {
+    /// Unit cubes centred on the given chunk-local positions.
+    Cubes(Vec<Vec3>),
+    /// Triangle mesh in chunk-local space.
+    TriMesh(Vec<Vec3>, Vec<[u32; 3]>),
+}
+
+impl ChunkCollider {
+    pub fn build(self) -> Collider {
+        match self {
+            ChunkCollider::Cubes(centers) => Collider::compound(
+                centers
+                    .into_iter()
+                    .map(|center| (center, Quat::IDENTITY, Collider::cuboid(0.5, 0.5, 0.5)))
+                    .collect(),
+            ),
+            ChunkCollider::TriMesh(vertices, triangles) => Collider::trimesh(vertices, triangles),
+        }
+    }
+}
+
+/// Output of the meshers: vertex, index and collider data that does not need a renderer.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    pub collider: Option<ChunkCollider>,
+}
+
+impl ChunkMeshData {
+    /// Converts the data into a render mesh and a physics collider.
+    pub fn build(self) -> (Mesh, Option<Collider>) {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+
+        (mesh, self.collider.map(ChunkCollider::build))
+    }
+}
+
+/// Number of detail levels. Level `n` meshes the chunk from blocks downsampled by `2^n`.
+pub const LOD_LEVELS: u32 = 4;
+
+/// Downsamples the blocks by `step` on every axis. A cell is solid when at least half of
+/// its blocks are, and takes the most common solid block inside it.
+fn downsample_blocks(blocks: &[u8], step: usize) -> Vec<u8> {
+    let size = CHUNK_SIZE / step;
+    let mut cells = vec![0; size * size * size];
+    let mut counts = [0u32; 256];
+
+    for cx in 0..size {
+        for cy in 0..size {
+            for cz in 0..size {
+                counts.fill(0);
+                let mut solid = 0;
+
+                for x in cx * step..(cx + 1) * step {
+                    for y in cy * step..(cy + 1) * step {
+                        for z in cz * step..(cz + 1) * step {
+                            let block = blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE];
+
+                            if block != 0 {
+                                counts[block as usize] += 1;
+                                solid += 1;
+                            }
+                        }
+                    }
+                }
+
+                if solid * 2 >= step * step * step {
+                    let (block, _) = counts
+                        .iter()
+                        .enumerate()
+                        .skip(1)
+                        .max_by_key(|(_, count)| **count)
+                        .unwrap();
+
+                    cells[cx + cy * size + cz * size * size] = block as u8;
+                }
+            }
+        }
+    }
+
+    cells
+}
+
+/// Builds the mesh of a chunk at the given detail level.
+///
+/// Level 0 is full resolution and is the only level with a collider. Coarser levels close
+/// their borders with skirts hanging one cell below the surface instead of full walls, so
+/// the seams against finer neighbours stay covered.
+pub fn build_chunk_mesh(chunk: &Chunk, lod: u32) -> ChunkMeshData {
+    let mut colliders: Vec<Vec3> = vec![];
 
     let mut positions: Vec<[f32; 3]> = vec![];
     let mut normals: Vec<[f32; 3]> = vec![];
@@ -67,72 +200,86 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
     let mut uvs: Vec<[f32; 2]> = vec![];
     let mut indices: Vec<u32> = vec![];
 
-    let at = |x: usize, y: usize, z: usize| -> u8 {
-        let blocks = chunk.blocks.as_ref().read();
-        blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE]
+    let step = 1 << lod.min(LOD_LEVELS - 1);
+    let size = CHUNK_SIZE / step;
+    let scale = step as f32;
+
+    let cells = if step == 1 {
+        chunk.blocks.as_ref().read().clone()
+    } else {
+        downsample_blocks(&chunk.blocks.as_ref().read(), step)
     };
 
-    for ix in 0..CHUNK_SIZE {
-        for iy in 0..CHUNK_SIZE {
-            for iz in 0..CHUNK_SIZE {
+    let at = |x: usize, y: usize, z: usize| -> u8 { cells[x + y * size + z * size * size] };
+
+    for ix in 0..size {
+        for iy in 0..size {
+            for iz in 0..size {
                 let block = at(ix, iy, iz);
 
                 if block == 0 {
                     continue;
                 }
 
-                // add collider if any side is exposed
-                if (ix == 0 || ix == CHUNK_SIZE - 1 || at(ix - 1, iy, iz) == 0)
-                    || (ix == 0 || ix == CHUNK_SIZE - 1 || at(ix + 1, iy, iz) == 0)
-                    || (iy == 0 || iy == CHUNK_SIZE - 1 || at(ix, iy - 1, iz) == 0)
-                    || (iy == 0 || iy == CHUNK_SIZE - 1 || at(ix, iy + 1, iz) == 0)
-                    || (iz == 0 || iz == CHUNK_SIZE - 1 || at(ix, iy, iz - 1) == 0)
-                    || (iz == 0 || iz == CHUNK_SIZE - 1 || at(ix, iy, iz + 1) == 0)
+                // faces show against air, and solid blocks also show through liquids
+                let open = |neighbour: u8| {
+                    neighbour == block::AIR
+                        || (block::is_liquid(neighbour) && !block::is_liquid(block))
+                };
+
+                let exposed = |neighbour: u8| !block::is_solid(neighbour);
+
+                // add collider if any side is exposed, liquids are swum through
+                if step == 1
+                    && block::is_solid(block)
+                    && ((ix == 0 || ix == size - 1 || exposed(at(ix - 1, iy, iz)))
+                        || (ix == 0 || ix == size - 1 || exposed(at(ix + 1, iy, iz)))
+                        || (iy == 0 || iy == size - 1 || exposed(at(ix, iy - 1, iz)))
+                        || (iy == 0 || iy == size - 1 || exposed(at(ix, iy + 1, iz)))
+                        || (iz == 0 || iz == size - 1 || exposed(at(ix, iy, iz - 1)))
+                        || (iz == 0 || iz == size - 1 || exposed(at(ix, iy, iz + 1))))
                 {
-                    colliders.push((
-                        Vec3::new(ix as f32 + 0.5, iy as f32 + 0.5, iz as f32 + 0.5),
-                        Quat::IDENTITY,
-                        Collider::cuboid(0.5, 0.5, 0.5),
-                    ));
+                    colliders.push(Vec3::new(ix as f32 + 0.5, iy as f32 + 0.5, iz as f32 + 0.5));
                 }
 
-                let x = ix as f32;
-                let y = iy as f32;
-                let z = iz as f32;
+                // coarse levels only close their borders at the surface, with a skirt
+                let surface = iy == size - 1 || open(at(ix, iy + 1, iz));
+                let border = step == 1 || surface;
+                let skirt = if step > 1 { scale } else { 0.0 };
 
-                let mut add_face = |face: [[f32; 3]; 4], normal: [f32; 3], uv: [[f32; 2]; 4]| {
-                    const COLOR_WATER: [f32; 4] = [0.106, 0.192, 0.549, 1.0];
-                    const COLOR_GRASS: [f32; 4] = [0.102, 0.631, 0.259, 1.0];
+                let x = ix as f32 * scale;
+                let y = iy as f32 * scale;
+                let z = iz as f32 * scale;
 
-                    if block == 1 {
-                        colors.extend(&[COLOR_WATER, COLOR_WATER, COLOR_WATER, COLOR_WATER]);
-                    } else if block == 2 {
-                        colors.extend(&[COLOR_GRASS, COLOR_GRASS, COLOR_GRASS, COLOR_GRASS]);
-                    }
+                let mut add_face =
+                    |face: [[f32; 3]; 4], normal: [f32; 3], uv: [[f32; 2]; 4], drop: f32| {
+                        colors.extend(&[block::info(block).color; 4]);
 
-                    let index = positions.len() as u32;
+                        let index = positions.len() as u32;
 
-                    positions.extend(&[
-                        [x + face[0][0], y + face[0][1], z + face[0][2]],
-                        [x + face[1][0], y + face[1][1], z + face[1][2]],
-                        [x + face[2][0], y + face[2][1], z + face[2][2]],
-                        [x + face[3][0], y + face[3][1], z + face[3][2]],
-                    ]);
+                        // vertices on the bottom edge are pulled down by `drop` to form skirts
+                        positions.extend(face.map(|v| {
+                            [
+                                x + v[0] * scale,
+                                y + v[1] * scale - if v[1] == 0.0 { drop } else { 0.0 },
+                                z + v[2] * scale,
+                            ]
+                        }));
 
-                    normals.extend(&[normal, normal, normal, normal]);
+                        normals.extend(&[normal, normal, normal, normal]);
 
-                    uvs.extend(&[
-                        [uv[0][0], uv[0][1]],
-                        [uv[1][0], uv[1][1]],
-                        [uv[2][0], uv[2][1]],
-                        [uv[3][0], uv[3][1]],
-                    ]);
+                        uvs.extend(&[
+                            [uv[0][0], uv[0][1]],
+                            [uv[1][0], uv[1][1]],
+                            [uv[2][0], uv[2][1]],
+                            [uv[3][0], uv[3][1]],
+                        ]);
 
-                    indices.extend(&[index, index + 1, index + 2, index, index + 2, index + 3]);
-                };
+                        indices.extend(&[index, index + 1, index + 2, index, index + 2, index + 3]);
+                    };
 
                 // Front
-                if iz == 0 || at(ix, iy, iz - 1) == 0 {
+                if (iz == 0 && border) || (iz > 0 && open(at(ix, iy, iz - 1))) {
                     add_face(
                         [
                             [0.0, 0.0, 0.0],
@@ -142,11 +289,12 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [0.0, 0.0, -1.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        if iz == 0 { skirt } else { 0.0 },
                     );
                 }
 
                 // Back
-                if iz == 0 || iz == CHUNK_SIZE - 1 || at(ix, iy, iz + 1) == 0 {
+                if (iz == size - 1 && border) || (iz < size - 1 && open(at(ix, iy, iz + 1))) {
                     add_face(
                         [
                             [1.0, 0.0, 1.0],
@@ -156,11 +304,12 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [0.0, 0.0, 1.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        if iz == size - 1 { skirt } else { 0.0 },
                     );
                 }
 
                 // Left
-                if ix == 0 || at(ix - 1, iy, iz) == 0 {
+                if (ix == 0 && border) || (ix > 0 && open(at(ix - 1, iy, iz))) {
                     add_face(
                         [
                             [0.0, 0.0, 1.0],
@@ -170,11 +319,12 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [-1.0, 0.0, 0.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        if ix == 0 { skirt } else { 0.0 },
                     );
                 }
 
                 // Right
-                if ix == 0 || ix == CHUNK_SIZE - 1 || at(ix + 1, iy, iz) == 0 {
+                if (ix == size - 1 && border) || (ix < size - 1 && open(at(ix + 1, iy, iz))) {
                     add_face(
                         [
                             [1.0, 0.0, 0.0],
@@ -184,11 +334,12 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [1.0, 0.0, 0.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        if ix == size - 1 { skirt } else { 0.0 },
                     );
                 }
 
                 // Top
-                if iy == 0 || iy == CHUNK_SIZE - 1 || at(ix, iy + 1, iz) == 0 {
+                if surface {
                     add_face(
                         [
                             [0.0, 1.0, 0.0],
@@ -198,11 +349,12 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [0.0, 1.0, 0.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        0.0,
                     );
                 }
 
                 // Bottom
-                if iy == 0 || at(ix, iy - 1, iz) == 0 {
+                if iy == 0 || open(at(ix, iy - 1, iz)) {
                     add_face(
                         [
                             [0.0, 0.0, 1.0],
@@ -212,19 +364,236 @@ pub fn build_chunk_mesh(chunk: &Chunk) -> (Mesh, Collider) {
                         ],
                         [0.0, -1.0, 0.0],
                         [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+                        0.0,
                     );
                 }
             }
         }
     }
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-    mesh.set_indices(Some(Indices::U32(indices)));
+    ChunkMeshData {
+        positions,
+        normals,
+        colors,
+        uvs,
+        indices,
+        collider: if step == 1 {
+            Some(ChunkCollider::Cubes(colliders))
+        } else {
+            None
+        },
+    }
+}
+
+/// Mesh of the chunk at a detail level, with the collider of the full resolution blocks
+/// whatever the level, since the camera can be far from the player standing on it.
+pub fn build_mesh(chunk: &Chunk, mode: MeshingMode, lod: u32) -> ChunkMeshData {
+    let build = match mode {
+        MeshingMode::Blocky => build_chunk_mesh,
+        MeshingMode::Smooth => build_chunk_mesh_smooth,
+    };
+
+    let mut data = build(chunk, lod);
 
-    (mesh, Collider::compound(colliders))
+    if lod > 0 {
+        data.collider = build(chunk, 0).collider;
+    }
+
+    data
+}
+
+/// Builds a smooth mesh of the chunk density field with surface nets.
+///
+/// Every cell crossed by the surface gets one vertex at the mean of its edge crossings, and
+/// every crossed sample edge emits a quad joining the four cells around it. Edges are owned
+/// by the chunk whose cells lie on their positive side, so neighbouring chunks meet without
+/// gaps at full detail. Coarser levels sample the field with a stride.
+pub fn build_chunk_mesh_smooth(chunk: &Chunk, lod: u32) -> ChunkMeshData {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+    let mut colors: Vec<[f32; 4]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+
+    let step = 1 << lod.min(LOD_LEVELS - 1);
+    let size = CHUNK_SIZE / step;
+    let samples = size + 2;
+
+    let density = chunk.density.as_ref().read();
+    let blocks = chunk.blocks.as_ref().read();
+
+    if density.is_empty() {
+        return ChunkMeshData::default();
+    }
+
+    let at = |x: usize, y: usize, z: usize| -> f32 {
+        let [x, y, z] = [x, y, z].map(|i| (i * step).min(DENSITY_SIZE - 1));
+        density[x + y * DENSITY_SIZE + z * DENSITY_SIZE * DENSITY_SIZE]
+    };
+
+    let block_at = |x: usize, y: usize, z: usize| -> u8 {
+        let [x, y, z] = [x, y, z].map(|i| i.min(CHUNK_SIZE - 1));
+        blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE]
+    };
+
+    const CORNERS: [[usize; 3]; 8] = [
+        [0, 0, 0],
+        [1, 0, 0],
+        [0, 1, 0],
+        [1, 1, 0],
+        [0, 0, 1],
+        [1, 0, 1],
+        [0, 1, 1],
+        [1, 1, 1],
+    ];
+
+    const EDGES: [(usize, usize); 12] = [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ];
+
+    // vertex index of every cell crossed by the surface
+    let cells = samples - 1;
+    let mut cell_vertex = vec![u32::MAX; cells * cells * cells];
+
+    for cx in 0..cells {
+        for cy in 0..cells {
+            for cz in 0..cells {
+                let values = CORNERS.map(|c| at(cx + c[0], cy + c[1], cz + c[2]));
+
+                let inside = values.iter().filter(|value| **value > 0.0).count();
+
+                if inside == 0 || inside == 8 {
+                    continue;
+                }
+
+                let mut sum = Vec3::ZERO;
+                let mut crossings = 0;
+
+                for (a, b) in EDGES {
+                    if (values[a] > 0.0) == (values[b] > 0.0) {
+                        continue;
+                    }
+
+                    let t = values[a] / (values[a] - values[b]);
+                    let ca = Vec3::from(CORNERS[a].map(|i| i as f32));
+                    let cb = Vec3::from(CORNERS[b].map(|i| i as f32));
+
+                    sum += ca.lerp(cb, t);
+                    crossings += 1;
+                }
+
+                let local = sum / crossings as f32;
+
+                let gradient = Vec3::new(
+                    (values[1] - values[0])
+                        + (values[3] - values[2])
+                        + (values[5] - values[4])
+                        + (values[7] - values[6]),
+                    (values[2] - values[0])
+                        + (values[3] - values[1])
+                        + (values[6] - values[4])
+                        + (values[7] - values[5]),
+                    (values[4] - values[0])
+                        + (values[5] - values[1])
+                        + (values[6] - values[2])
+                        + (values[7] - values[3]),
+                );
+
+                let position = (Vec3::new(cx as f32, cy as f32, cz as f32) + local) * step as f32;
+
+                let bx = position.x as usize;
+                let by = position.y as usize;
+                let bz = position.z as usize;
+
+                let block = match block_at(bx, by, bz) {
+                    0 => block_at(bx, by.saturating_sub(1), bz),
+                    block => block,
+                };
+
+                cell_vertex[cx + cy * cells + cz * cells * cells] = positions.len() as u32;
+
+                positions.push(position.to_array());
+                normals.push((-gradient).normalize_or_zero().to_array());
+                colors.push(if block == 1 { COLOR_WATER } else { COLOR_GRASS });
+                uvs.push([position.x, position.z]);
+            }
+        }
+    }
+
+    let vertex = |x: usize, y: usize, z: usize| cell_vertex[x + y * cells + z * cells * cells];
+
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                let inside = at(x, y, z) > 0.0;
+
+                // edges along each axis, with the two other axes walking the cells around it
+                for axis in 0..3 {
+                    let mut end = [x, y, z];
+                    end[axis] += 1;
+
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+                    if end[axis] > size || [x, y, z][u] == 0 || [x, y, z][v] == 0 {
+                        continue;
+                    }
+
+                    if inside == (at(end[0], end[1], end[2]) > 0.0) {
+                        continue;
+                    }
+
+                    let quad = [[0, 0], [1, 0], [1, 1], [0, 1]].map(|[du, dv]| {
+                        let mut cell = [x, y, z];
+                        cell[u] -= 1 - du;
+                        cell[v] -= 1 - dv;
+                        vertex(cell[0], cell[1], cell[2])
+                    });
+
+                    if quad.contains(&u32::MAX) {
+                        continue;
+                    }
+
+                    if inside {
+                        indices.extend(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                    } else {
+                        indices.extend(&[quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+                    }
+                }
+            }
+        }
+    }
+
+    let collider = if step == 1 && !indices.is_empty() {
+        Some(ChunkCollider::TriMesh(
+            positions.iter().map(|p| Vec3::from(*p)).collect(),
+            indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+        ))
+    } else {
+        None
+    };
+
+    ChunkMeshData {
+        positions,
+        normals,
+        colors,
+        uvs,
+        indices,
+        collider,
+    }
 }
 
 pub fn spawn_chunk(
@@ -241,22 +610,262 @@ pub fn spawn_chunk(
         state.chunk.position.z as f32 * CHUNK_SIZE as f32,
     ));
 
-    commands
-        .spawn((
-            state.chunk,
-            PbrBundle {
-                mesh: state.mesh,
-                material: materials
-                    .add(StandardMaterial {
-                        base_color: Color::rgb(0.9, 0.9, 0.9),
-                        base_color_texture: Some(block_texture.clone()),
-                        ..default()
-                    })
-                    .into(),
-                transform: transform,
+    let mut entity = commands.spawn((
+        state.chunk,
+        PbrBundle {
+            mesh: state.mesh,
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.9, 0.9, 0.9),
+                base_color_texture: Some(block_texture.clone()),
                 ..default()
-            },
-            state.collider,
-        ))
-        .id()
+            }),
+            transform,
+            ..default()
+        },
+    ));
+
+    if let Some(collider) = state.collider {
+        entity.insert(collider);
+    }
+
+    entity.id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_with(blocks: &[(usize, usize, usize, u8)]) -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for &(x, y, z, block) in blocks {
+            chunk.set_block(x, y, z, block);
+        }
+        chunk
+    }
+
+    impl ChunkMeshData {
+        fn face_count(&self) -> usize {
+            self.indices.len() / 6
+        }
+    }
+
+    fn cube_count(data: &ChunkMeshData) -> usize {
+        match &data.collider {
+            Some(ChunkCollider::Cubes(cubes)) => cubes.len(),
+            _ => panic!("expected a cube collider"),
+        }
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        let data = build_chunk_mesh(&Chunk::new(IVec3::ZERO), 0);
+
+        assert_eq!(data.face_count(), 0);
+        assert!(data.positions.is_empty());
+        assert_eq!(cube_count(&data), 0);
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let data = build_chunk_mesh(&chunk_with(&[(10, 10, 10, 2)]), 0);
+
+        assert_eq!(data.face_count(), 6);
+        assert_eq!(data.positions.len(), 24);
+        assert_eq!(data.normals.len(), 24);
+        assert_eq!(data.colors.len(), 24);
+        assert_eq!(data.uvs.len(), 24);
+        assert_eq!(
+            data.collider,
+            Some(ChunkCollider::Cubes(vec![Vec3::new(10.5, 10.5, 10.5)]))
+        );
+    }
+
+    #[test]
+    fn shared_faces_are_culled() {
+        let pair = build_chunk_mesh(&chunk_with(&[(10, 10, 10, 2), (11, 10, 10, 2)]), 0);
+        assert_eq!(pair.face_count(), 10);
+
+        let column = build_chunk_mesh(
+            &chunk_with(&[(10, 10, 10, 2), (10, 11, 10, 2), (10, 12, 10, 2)]),
+            0,
+        );
+        assert_eq!(column.face_count(), 14);
+    }
+
+    #[test]
+    fn buried_block_has_no_faces_or_collider() {
+        let mut blocks = vec![];
+        for x in 9..12 {
+            for y in 9..12 {
+                for z in 9..12 {
+                    blocks.push((x, y, z, 2));
+                }
+            }
+        }
+
+        let data = build_chunk_mesh(&chunk_with(&blocks), 0);
+
+        assert_eq!(data.face_count(), 6 * 9);
+        assert_eq!(cube_count(&data), 26);
+    }
+
+    #[test]
+    fn chunk_borders_are_closed() {
+        let low = build_chunk_mesh(&chunk_with(&[(0, 0, 0, 2)]), 0);
+        assert_eq!(low.face_count(), 6);
+
+        let high = build_chunk_mesh(
+            &chunk_with(&[(CHUNK_SIZE - 1, CHUNK_SIZE - 1, CHUNK_SIZE - 1, 2)]),
+            0,
+        );
+        assert_eq!(high.face_count(), 6);
+
+        let full = Chunk::new(IVec3::ZERO);
+        full.blocks.as_ref().write().fill(2);
+        let data = build_chunk_mesh(&full, 0);
+
+        assert_eq!(data.face_count(), 6 * CHUNK_SIZE * CHUNK_SIZE);
+        assert_eq!(
+            cube_count(&data),
+            CHUNK_SIZE.pow(3) - (CHUNK_SIZE - 2).pow(3)
+        );
+    }
+
+    #[test]
+    fn faces_point_outwards() {
+        let data = build_chunk_mesh(&chunk_with(&[(4, 4, 4, 1)]), 0);
+
+        for (face, normal) in data.indices.chunks(6).zip(data.normals.chunks(4)) {
+            let [a, b, c] =
+                [face[0], face[1], face[2]].map(|i| Vec3::from(data.positions[i as usize]));
+            let winding = (b - a).cross(c - a).normalize();
+
+            assert_eq!(winding, Vec3::from(normal[0]));
+        }
+    }
+
+    #[test]
+    fn block_ids_pick_vertex_colors() {
+        let data = build_chunk_mesh(&chunk_with(&[(1, 1, 1, 1), (5, 5, 5, 2)]), 0);
+
+        assert_eq!(data.colors[..24], [COLOR_WATER; 24]);
+        assert_eq!(data.colors[24..], [COLOR_GRASS; 24]);
+    }
+
+    #[test]
+    fn liquids_have_no_collider_and_show_what_they_cover() {
+        // grass at the bottom of a pool
+        let data = build_chunk_mesh(&chunk_with(&[(5, 5, 5, 2), (5, 6, 5, 1)]), 0);
+
+        assert_eq!(
+            data.collider,
+            Some(ChunkCollider::Cubes(vec![Vec3::new(5.5, 5.5, 5.5)]))
+        );
+
+        // the grass top is drawn under the water, the water bottom is not
+        assert_eq!(data.face_count(), 6 + 5);
+    }
+
+    #[test]
+    fn coarse_meshes_keep_full_resolution_colliders() {
+        let chunk = chunk_with(&[(1, 1, 1, 2), (2, 1, 1, 2)]);
+
+        let data = build_mesh(&chunk, MeshingMode::Blocky, LOD_LEVELS - 1);
+
+        match data.collider {
+            Some(ChunkCollider::Cubes(centers)) => assert_eq!(centers.len(), 2),
+            _ => panic!("expected a cube collider"),
+        }
+    }
+
+    #[test]
+    fn coarse_levels_downsample_and_drop_colliders() {
+        let full = Chunk::new(IVec3::ZERO);
+        full.blocks.as_ref().write().fill(2);
+
+        let data = build_chunk_mesh(&full, 1);
+        let size = CHUNK_SIZE / 2;
+
+        // top and bottom, plus one skirted border face per surface cell on every side
+        assert_eq!(data.face_count(), 2 * size * size + 4 * size);
+        assert!(data.collider.is_none());
+
+        let lowest = data
+            .positions
+            .iter()
+            .map(|p| p[1])
+            .fold(f32::INFINITY, f32::min);
+        assert_eq!(lowest, 0.0);
+
+        let skirt_bottom = data
+            .positions
+            .iter()
+            .filter(|p| p[0] == 0.0)
+            .map(|p| p[1])
+            .filter(|y| *y > 0.0)
+            .fold(f32::INFINITY, f32::min);
+        assert_eq!(skirt_bottom, CHUNK_SIZE as f32 - 4.0);
+    }
+
+    #[test]
+    fn downsampling_keeps_majority_block() {
+        let mut blocks = vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        let index = |x: usize, y: usize, z: usize| x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE;
+
+        // first cell: 5 of 8 blocks solid, mostly water
+        for (i, (x, y, z)) in [(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0), (0, 0, 1)]
+            .into_iter()
+            .enumerate()
+        {
+            blocks[index(x, y, z)] = if i < 3 { 1 } else { 2 };
+        }
+
+        // second cell: a single block is not enough to keep it solid
+        blocks[index(2, 0, 0)] = 2;
+
+        let cells = downsample_blocks(&blocks, 2);
+
+        assert_eq!(cells[0], 1);
+        assert_eq!(cells[1], 0);
+    }
+
+    #[test]
+    fn smooth_mesh_follows_density_plane() {
+        let mut chunk = Chunk::with_density(IVec3::ZERO);
+        for x in 0..DENSITY_SIZE {
+            for y in 0..DENSITY_SIZE {
+                for z in 0..DENSITY_SIZE {
+                    chunk.set_density(x, y, z, 20.5 - y as f32);
+                }
+            }
+        }
+
+        let data = build_chunk_mesh_smooth(&chunk, 0);
+
+        assert_eq!(data.face_count(), CHUNK_SIZE * CHUNK_SIZE);
+        assert!(data.positions.iter().all(|p| (p[1] - 20.5).abs() < 1e-5));
+        assert!(data.normals.iter().all(|n| *n == [0.0, 1.0, 0.0]));
+
+        for triangle in data.indices.chunks(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+                .map(|i| Vec3::from(data.positions[i as usize]));
+            assert!((b - a).cross(c - a).y > 0.0);
+        }
+
+        match data.collider {
+            Some(ChunkCollider::TriMesh(vertices, triangles)) => {
+                assert_eq!(vertices.len(), data.positions.len());
+                assert_eq!(triangles.len(), data.indices.len() / 3);
+            }
+            _ => panic!("expected a trimesh collider"),
+        }
+    }
+
+    #[test]
+    fn smooth_mesh_needs_density() {
+        let data = build_chunk_mesh_smooth(&chunk_with(&[(1, 1, 1, 2)]), 0);
+
+        assert_eq!(data.face_count(), 0);
+        assert!(data.collider.is_none());
+    }
 }
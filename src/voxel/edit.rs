@@ -0,0 +1,668 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use super::block;
+use super::chunk::CHUNK_SIZE;
+use super::schematic::{check_selection, Clipboard, Schematic};
+use super::world::{RayHit, World};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tool {
+    /// Breaks and places single blocks from the hotbar.
+    #[default]
+    Block,
+    Brush,
+    /// Picks the corners of the `Selection`.
+    Select,
+    FloodFill,
+    /// Pastes the `Clipboard`.
+    Paste,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushShape {
+    #[default]
+    Sphere,
+    Cube,
+    /// Upright cylinder, as high as it is wide.
+    Cylinder,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushMode {
+    /// Fills the empty blocks of the brush.
+    #[default]
+    Add,
+    Remove,
+    /// Changes the solid blocks of the brush, leaving the empty ones.
+    Replace,
+    /// Rounds the terrain off, each block taking what most of its neighbours are.
+    Smooth,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+    pub radius: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::default(),
+            mode: BrushMode::default(),
+            radius: 3.0,
+        }
+    }
+}
+
+impl Brush {
+    /// Positions of the blocks inside the brush centred on `center`.
+    pub fn positions(&self, center: IVec3) -> Vec<IVec3> {
+        let extent = self.radius.floor() as i32;
+        let mut positions = vec![];
+
+        for x in -extent..=extent {
+            for y in -extent..=extent {
+                for z in -extent..=extent {
+                    let offset = Vec3::new(x as f32, y as f32, z as f32);
+
+                    let inside = match self.shape {
+                        BrushShape::Sphere => offset.length() <= self.radius,
+                        BrushShape::Cube => true,
+                        BrushShape::Cylinder => offset.xz().length() <= self.radius,
+                    };
+
+                    if inside {
+                        positions.push(center + IVec3::new(x, y, z));
+                    }
+                }
+            }
+        }
+
+        positions
+    }
+}
+
+/// Two corners of a box of blocks, both inside it.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Selection {
+    pub corners: [Option<IVec3>; 2],
+}
+
+impl Selection {
+    /// Lowest and highest corner, once both are picked.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        let [Some(a), Some(b)] = self.corners else {
+            return None;
+        };
+
+        Some((a.min(b), a.max(b)))
+    }
+}
+
+/// Editing tool used with the break and place actions, and its settings.
+#[derive(Resource, Debug)]
+pub struct EditTools {
+    pub tool: Tool,
+    pub brush: Brush,
+    /// Block the brushes and fills place.
+    pub block: u8,
+    /// Most blocks a flood fill changes, so filling the open air stays bounded.
+    pub flood_limit: usize,
+    /// Block the player aims at, where tools are used and pastes previewed.
+    pub aim: Option<RayHit>,
+}
+
+impl Default for EditTools {
+    fn default() -> Self {
+        Self {
+            tool: Tool::default(),
+            brush: Brush::default(),
+            block: block::GRASS,
+            flood_limit: 4096,
+            aim: None,
+        }
+    }
+}
+
+impl World {
+    /// Changes many blocks as one transaction of the history. Every chunk touched is
+    /// remeshed once. Returns how many blocks changed.
+    pub fn set_blocks(&mut self, blocks: impl IntoIterator<Item = (IVec3, u8)>) -> usize {
+        self.history.begin();
+
+        let changed = blocks
+            .into_iter()
+            .filter(|(position, block)| {
+                self.set_block(*position, *block)
+                    .is_some_and(|previous| previous != *block)
+            })
+            .count();
+
+        self.history.end();
+        changed
+    }
+}
+
+/// Applies a brush centred on `center`, placing `fill` where it adds blocks. Returns how
+/// many blocks changed.
+pub fn apply_brush(world: &mut World, brush: &Brush, center: IVec3, fill: u8) -> usize {
+    let positions = brush.positions(center);
+
+    // every block is decided from the world as it was before the brush
+    let changes: Vec<(IVec3, u8)> = positions
+        .iter()
+        .filter_map(|position| {
+            let current = world.get_block(*position)?;
+
+            let block = match brush.mode {
+                BrushMode::Add if current == block::AIR || block::is_liquid(current) => fill,
+                BrushMode::Remove if current != block::AIR => block::AIR,
+                BrushMode::Replace if block::is_solid(current) => fill,
+                BrushMode::Smooth => smoothed(world, *position),
+                _ => current,
+            };
+
+            (block != current).then_some((*position, block))
+        })
+        .collect();
+
+    world.set_blocks(changes)
+}
+
+/// Block most of the 26 neighbours of `position` agree on: the most common solid block
+/// when most are solid, and air otherwise.
+fn smoothed(world: &World, position: IVec3) -> u8 {
+    let mut solid = vec![];
+    let mut total = 0;
+
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                if x == 0 && y == 0 && z == 0 {
+                    continue;
+                }
+
+                if let Some(block) = world.get_block(position + IVec3::new(x, y, z)) {
+                    total += 1;
+
+                    if block::is_solid(block) {
+                        solid.push(block);
+                    }
+                }
+            }
+        }
+    }
+
+    let current = world.get_block(position).unwrap_or(block::AIR);
+
+    if solid.len() * 2 > total {
+        if block::is_solid(current) {
+            return current;
+        }
+
+        solid.sort_unstable();
+        let mut best = (0, solid[0]);
+        for run in solid.chunk_by(|a, b| a == b) {
+            if run.len() > best.0 {
+                best = (run.len(), run[0]);
+            }
+        }
+        best.1
+    } else if solid.len() * 2 < total && block::is_solid(current) {
+        block::AIR
+    } else {
+        current
+    }
+}
+
+/// Fills the box between two corners, both included. Returns how many blocks changed, or
+/// fails for boxes too large to select.
+pub fn fill_box(world: &mut World, a: IVec3, b: IVec3, fill: u8) -> io::Result<usize> {
+    let (min, max) = (a.min(b), a.max(b));
+    check_selection(max - min + 1)?;
+
+    let positions = (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+    });
+
+    Ok(world.set_blocks(positions.map(|position| (position, fill))))
+}
+
+/// Changes the blocks connected to `start` by their faces that are the same as it, at
+/// most `limit` of them. Returns how many blocks changed.
+pub fn flood_fill(world: &mut World, start: IVec3, fill: u8, limit: usize) -> usize {
+    // outside the chunks the world reads as air, which would never run out
+    let height = 0..CHUNK_SIZE as i32;
+
+    if !height.contains(&start.y) {
+        return 0;
+    }
+
+    let Some(target) = world.get_block(start) else {
+        return 0;
+    };
+
+    if target == fill {
+        return 0;
+    }
+
+    const NEIGHBOURS: [IVec3; 6] = [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ];
+
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut region = vec![];
+
+    while let Some(position) = queue.pop_front() {
+        if region.len() >= limit {
+            break;
+        }
+
+        region.push((position, fill));
+
+        for neighbour in NEIGHBOURS.map(|offset| position + offset) {
+            if height.contains(&neighbour.y)
+                && world.get_block(neighbour) == Some(target)
+                && visited.insert(neighbour)
+            {
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    world.set_blocks(region)
+}
+
+/// Settings of the editing tools, fills and copies of the selection, and the clipboard.
+pub fn tools_window(
+    mut contexts: EguiContexts,
+    mut tools: ResMut<EditTools>,
+    mut selection: ResMut<Selection>,
+    mut clipboard: ResMut<Clipboard>,
+    mut world: ResMut<World>,
+    mut status: Local<String>,
+) {
+    egui::Window::new("Tools").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut tools.tool, Tool::Block, "Block");
+            ui.selectable_value(&mut tools.tool, Tool::Brush, "Brush");
+            ui.selectable_value(&mut tools.tool, Tool::Select, "Select");
+            ui.selectable_value(&mut tools.tool, Tool::FloodFill, "Flood fill");
+            ui.selectable_value(&mut tools.tool, Tool::Paste, "Paste");
+        });
+
+        egui::ComboBox::from_label("Block")
+            .selected_text(block::info(tools.block).name)
+            .show_ui(ui, |ui| {
+                for (id, info) in block::BLOCKS.iter().enumerate().skip(1) {
+                    ui.selectable_value(&mut tools.block, id as u8, info.name);
+                }
+            });
+
+        ui.separator();
+
+        match tools.tool {
+            Tool::Block => {
+                ui.label("Break and place single blocks from the hotbar.");
+            }
+            Tool::Brush => {
+                let brush = &mut tools.brush;
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut brush.shape, BrushShape::Sphere, "Sphere");
+                    ui.selectable_value(&mut brush.shape, BrushShape::Cube, "Cube");
+                    ui.selectable_value(&mut brush.shape, BrushShape::Cylinder, "Cylinder");
+                });
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut brush.mode, BrushMode::Add, "Add");
+                    ui.selectable_value(&mut brush.mode, BrushMode::Remove, "Remove");
+                    ui.selectable_value(&mut brush.mode, BrushMode::Replace, "Replace");
+                    ui.selectable_value(&mut brush.mode, BrushMode::Smooth, "Smooth");
+                });
+                ui.add(egui::Slider::new(&mut brush.radius, 0.5..=12.0).text("Radius"));
+                ui.label("Place applies the brush, break removes with its shape.");
+            }
+            Tool::Select => {
+                for (i, corner) in selection.corners.iter().enumerate() {
+                    ui.label(match corner {
+                        Some(corner) => format!("Corner {}: {}", i + 1, corner),
+                        None => format!("Corner {}: break or place to pick", i + 1),
+                    });
+                }
+
+                let bounds = selection.bounds();
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(bounds.is_some(), egui::Button::new("Fill"))
+                        .clicked()
+                    {
+                        let (min, max) = bounds.unwrap();
+                        if let Err(error) = fill_box(&mut world, min, max, tools.block) {
+                            *status = format!("Could not fill: {}", error);
+                        }
+                    }
+                    if ui
+                        .add_enabled(bounds.is_some(), egui::Button::new("Clear"))
+                        .clicked()
+                    {
+                        let (min, max) = bounds.unwrap();
+                        if let Err(error) = fill_box(&mut world, min, max, block::AIR) {
+                            *status = format!("Could not clear: {}", error);
+                        }
+                    }
+                    if ui
+                        .add_enabled(bounds.is_some(), egui::Button::new("Copy"))
+                        .clicked()
+                    {
+                        let (min, max) = bounds.unwrap();
+                        match Schematic::copy(&world, min, max) {
+                            Ok(schematic) => clipboard.schematic = Some(schematic),
+                            Err(error) => *status = format!("Could not copy: {}", error),
+                        }
+                    }
+                    if ui.button("Deselect").clicked() {
+                        *selection = Selection::default();
+                    }
+                });
+
+                ui.separator();
+                path_field(ui, clipboard.bypass_change_detection());
+
+                if ui
+                    .add_enabled(bounds.is_some(), egui::Button::new("Export"))
+                    .clicked()
+                {
+                    let (min, max) = bounds.unwrap();
+                    let path = PathBuf::from(&clipboard.path);
+
+                    *status = match Schematic::copy(&world, min, max)
+                        .and_then(|schematic| schematic.save(&path))
+                    {
+                        Ok(()) => format!("Exported {}", path.display()),
+                        Err(error) => format!("Could not export: {}", error),
+                    };
+                }
+
+                if !status.is_empty() {
+                    ui.label(status.as_str());
+                }
+                ui.label("Export saves the selection, as a MagicaVoxel model for .vox files.");
+            }
+            Tool::FloodFill => {
+                ui.add(
+                    egui::Slider::new(&mut tools.flood_limit, 1..=65536)
+                        .logarithmic(true)
+                        .text("Limit"),
+                );
+                ui.label("Place fills the empty space touched, break empties the block's region.");
+            }
+            Tool::Paste => {
+                let size = clipboard.schematic.as_ref().map(|schematic| schematic.size);
+
+                ui.label(match size {
+                    Some(size) => format!("Clipboard: {} x {} x {}", size.x, size.y, size.z),
+                    None => "Clipboard: empty, copy a selection or load a file".to_string(),
+                });
+
+                ui.add_enabled_ui(size.is_some(), |ui| {
+                    ui.horizontal(|ui| {
+                        let rotate = ui.button("Rotate").clicked();
+                        let mirror_x = ui.button("Mirror X").clicked();
+                        let mirror_z = ui.button("Mirror Z").clicked();
+
+                        if !(rotate || mirror_x || mirror_z) {
+                            return;
+                        }
+
+                        if let Some(schematic) = clipboard.schematic.as_mut() {
+                            if rotate {
+                                *schematic = schematic.rotated();
+                            }
+                            if mirror_x {
+                                *schematic = schematic.mirrored(0);
+                            }
+                            if mirror_z {
+                                *schematic = schematic.mirrored(2);
+                            }
+                        }
+                    });
+                    // the preview is only rebuilt when the schematic changes
+                    let settings = clipboard.bypass_change_detection();
+                    ui.checkbox(&mut settings.include_air, "Paste air");
+                });
+
+                ui.separator();
+
+                path_field(ui, clipboard.bypass_change_detection());
+
+                ui.horizontal(|ui| {
+                    let path = PathBuf::from(&clipboard.path);
+
+                    if let Some(schematic) = &clipboard.schematic {
+                        if ui.button("Save").clicked() {
+                            *status = match schematic.save(&path) {
+                                Ok(()) => format!("Saved {}", path.display()),
+                                Err(error) => format!("Could not save: {}", error),
+                            };
+                        }
+                    }
+                    if ui.button("Load").clicked() {
+                        *status = match Schematic::load(&path) {
+                            Ok(schematic) => {
+                                clipboard.schematic = Some(schematic);
+                                format!("Loaded {}", path.display())
+                            }
+                            Err(error) => format!("Could not load: {}", error),
+                        };
+                    }
+                });
+
+                if !status.is_empty() {
+                    ui.label(status.as_str());
+                }
+                ui.label(
+                    "Place pastes. Sponge .schem files are imported, MagicaVoxel .vox files \
+                     imported and saved.",
+                );
+            }
+        }
+    });
+}
+
+/// Text field of the file the clipboard is saved to and loaded from. Editing it does not
+/// rebuild the preview when given the clipboard without change detection.
+fn path_field(ui: &mut egui::Ui, clipboard: &mut Clipboard) {
+    ui.horizontal(|ui| {
+        ui.label("File");
+        ui.text_edit_singleline(&mut clipboard.path);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::Chunk;
+
+    /// World of one chunk with grass up to `ground`, not included.
+    fn world_with_ground(ground: usize) -> World {
+        let mut world = World::new();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..ground {
+                for z in 0..CHUNK_SIZE {
+                    chunk.set_block(x, y, z, block::GRASS);
+                }
+            }
+        }
+        world.insert_loaded_chunk(chunk);
+
+        world
+    }
+
+    fn count(world: &World, block: u8) -> usize {
+        world
+            .chunks
+            .iter()
+            .map(|(_, state)| {
+                state
+                    .chunk
+                    .blocks
+                    .as_ref()
+                    .read()
+                    .iter()
+                    .filter(|b| **b == block)
+                    .count()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn brush_shapes() {
+        let brush = |shape| Brush {
+            shape,
+            radius: 2.0,
+            ..default()
+        };
+
+        assert_eq!(brush(BrushShape::Cube).positions(IVec3::ZERO).len(), 125);
+        assert_eq!(brush(BrushShape::Sphere).positions(IVec3::ZERO).len(), 33);
+        assert_eq!(brush(BrushShape::Cylinder).positions(IVec3::ZERO).len(), 65);
+
+        let sphere = brush(BrushShape::Sphere).positions(IVec3::new(10, 10, 10));
+        assert!(sphere.contains(&IVec3::new(12, 10, 10)));
+        assert!(!sphere.contains(&IVec3::new(12, 12, 10)));
+    }
+
+    #[test]
+    fn brushes_add_remove_and_replace_as_one_undo() {
+        let mut world = world_with_ground(10);
+        let grass = count(&world, block::GRASS);
+        let center = IVec3::new(20, 10, 20);
+        let mut brush = Brush {
+            shape: BrushShape::Cube,
+            radius: 1.0,
+            ..default()
+        };
+
+        // only the upper half of the cube is empty
+        assert_eq!(apply_brush(&mut world, &brush, center, block::WATER), 18);
+        assert_eq!(count(&world, block::WATER), 18);
+        assert_eq!(world.chunks_to_remesh.len(), 1);
+
+        // the water is not solid, so only the ground below is replaced
+        brush.mode = BrushMode::Replace;
+        assert_eq!(apply_brush(&mut world, &brush, center, block::WATER), 9);
+        assert_eq!(count(&world, block::WATER), 27);
+
+        brush.mode = BrushMode::Remove;
+        assert_eq!(apply_brush(&mut world, &brush, center, block::GRASS), 27);
+
+        world.undo();
+        world.undo();
+        world.undo();
+        assert_eq!(count(&world, block::GRASS), grass);
+        assert_eq!(count(&world, block::WATER), 0);
+    }
+
+    #[test]
+    fn smoothing_flattens_bumps_and_fills_pits() {
+        let mut world = world_with_ground(10);
+        let bump = IVec3::new(20, 10, 20);
+        let pit = IVec3::new(40, 9, 40);
+
+        world.set_block(bump, block::GRASS);
+        world.set_block(pit, block::AIR);
+
+        let brush = Brush {
+            mode: BrushMode::Smooth,
+            radius: 1.0,
+            ..default()
+        };
+
+        apply_brush(&mut world, &brush, bump, block::GRASS);
+        apply_brush(&mut world, &brush, pit, block::GRASS);
+
+        assert_eq!(world.get_block(bump), Some(block::AIR));
+        assert_eq!(world.get_block(pit), Some(block::GRASS));
+    }
+
+    #[test]
+    fn box_and_flood_fills() {
+        let mut world = world_with_ground(10);
+
+        assert_eq!(
+            fill_box(
+                &mut world,
+                IVec3::new(5, 12, 5),
+                IVec3::new(3, 10, 4),
+                block::GRASS
+            )
+            .unwrap(),
+            3 * 3 * 2
+        );
+
+        // a cave closed all around
+        fill_box(
+            &mut world,
+            IVec3::new(20, 3, 20),
+            IVec3::new(22, 4, 22),
+            block::AIR,
+        )
+        .unwrap();
+
+        assert_eq!(
+            flood_fill(&mut world, IVec3::new(21, 3, 21), block::WATER, 100),
+            18
+        );
+        assert_eq!(world.get_block(IVec3::new(21, 5, 21)), Some(block::GRASS));
+
+        // the open air stops at the limit
+        assert_eq!(
+            flood_fill(&mut world, IVec3::new(40, 30, 40), block::WATER, 50),
+            50
+        );
+
+        // and at the top of the chunks, which it never starts above
+        let air = CHUNK_SIZE * CHUNK_SIZE * (CHUNK_SIZE - 10) - 3 * 3 * 2 - 50;
+        assert_eq!(
+            flood_fill(&mut world, IVec3::new(10, 30, 10), block::WATER, 2 * air),
+            air
+        );
+        assert_eq!(
+            flood_fill(
+                &mut world,
+                IVec3::new(40, CHUNK_SIZE as i32, 40),
+                block::GRASS,
+                50
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn oversized_boxes_are_refused() {
+        let mut world = world_with_ground(10);
+
+        let far = IVec3::new(100_000, 63, 100_000);
+        assert!(fill_box(&mut world, -far, far, block::AIR).is_err());
+        assert_eq!(world.get_block(IVec3::new(5, 5, 5)), Some(block::GRASS));
+        assert!(!world.undo());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use super::world::World;
+
+/// One block change, with the block before and after it so it can be undone and redone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockChange {
+    pub position: IVec3,
+    pub before: u8,
+    pub after: u8,
+}
+
+/// Block changes of the `World`, grouped into transactions undone and redone at once.
+/// Changes made outside a transaction are transactions of their own.
+#[derive(Clone, Debug)]
+pub struct History {
+    undo: VecDeque<Vec<BlockChange>>,
+    redo: Vec<Vec<BlockChange>>,
+    /// Changes of the open transaction, and how many times it was opened.
+    transaction: Vec<BlockChange>,
+    depth: usize,
+    /// Most transactions kept, the oldest are forgotten first.
+    pub max_size: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl History {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            transaction: Vec::new(),
+            depth: 0,
+            max_size,
+        }
+    }
+
+    /// Opens a transaction. Transactions opened inside it are part of it.
+    pub fn begin(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Closes the transaction opened last, keeping its changes once the outermost one
+    /// closes.
+    pub fn end(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+
+        if self.depth == 0 {
+            let transaction = std::mem::take(&mut self.transaction);
+            self.push(transaction);
+        }
+    }
+
+    pub fn record(&mut self, change: BlockChange) {
+        if self.depth > 0 {
+            self.transaction.push(change);
+        } else {
+            self.push(vec![change]);
+        }
+    }
+
+    fn push(&mut self, transaction: Vec<BlockChange>) {
+        if transaction.is_empty() || self.max_size == 0 {
+            return;
+        }
+
+        self.redo.clear();
+        self.undo.push_back(transaction);
+
+        while self.undo.len() > self.max_size {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Changes of the transaction `undo` would take, without taking it.
+    pub fn next_undo(&self) -> Option<&[BlockChange]> {
+        self.undo.back().map(Vec::as_slice)
+    }
+
+    /// Changes of the transaction `redo` would take, without taking it.
+    pub fn next_redo(&self) -> Option<&[BlockChange]> {
+        self.redo.last().map(Vec::as_slice)
+    }
+
+    /// Takes the last transaction to undo, as the changes reverting it in order.
+    pub fn undo(&mut self) -> Option<Vec<BlockChange>> {
+        let transaction = self.undo.pop_back()?;
+        let reverted = transaction
+            .iter()
+            .rev()
+            .map(|change| BlockChange {
+                position: change.position,
+                before: change.after,
+                after: change.before,
+            })
+            .collect();
+
+        self.redo.push(transaction);
+        Some(reverted)
+    }
+
+    /// Takes the last undone transaction, as the changes to apply again in order.
+    pub fn redo(&mut self) -> Option<Vec<BlockChange>> {
+        let transaction = self.redo.pop()?;
+
+        self.undo.push_back(transaction.clone());
+        Some(transaction)
+    }
+}
+
+/// Undoes the last edit with Ctrl+Z and redoes it with Ctrl+Y or Ctrl+Shift+Z, unless
+/// they are meant for an egui text field.
+pub fn undo_edits(keys: Res<Input<KeyCode>>, mut world: ResMut<World>, mut contexts: EguiContexts) {
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if keys.just_pressed(KeyCode::Y) || (shift && keys.just_pressed(KeyCode::Z)) {
+        world.redo();
+    } else if keys.just_pressed(KeyCode::Z) {
+        world.undo();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(x: i32, before: u8, after: u8) -> BlockChange {
+        BlockChange {
+            position: IVec3::new(x, 0, 0),
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn undo_reverts_in_reverse_order_and_redo_replays() {
+        let mut history = History::default();
+
+        history.begin();
+        history.record(change(0, 0, 2));
+        history.record(change(0, 2, 1));
+        history.end();
+
+        assert_eq!(history.undo(), Some(vec![change(0, 1, 2), change(0, 2, 0)]));
+        assert!(!history.can_undo());
+
+        assert_eq!(history.redo(), Some(vec![change(0, 0, 2), change(0, 2, 1)]));
+        assert!(!history.can_redo());
+        assert!(history.can_undo());
+    }
+
+    #[test]
+    fn nested_transactions_are_one() {
+        let mut history = History::default();
+
+        history.begin();
+        history.record(change(0, 0, 2));
+        history.begin();
+        history.record(change(1, 0, 2));
+        history.end();
+        history.record(change(2, 0, 2));
+        history.end();
+
+        assert_eq!(history.undo().unwrap().len(), 3);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_history() {
+        let mut history = History::default();
+
+        history.record(change(0, 0, 2));
+        history.undo();
+        assert!(history.can_redo());
+
+        history.record(change(1, 0, 2));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::new(2);
+
+        for x in 0..5 {
+            history.record(change(x, 0, 2));
+        }
+
+        assert_eq!(history.undo(), Some(vec![change(4, 2, 0)]));
+        assert_eq!(history.undo(), Some(vec![change(3, 2, 0)]));
+        assert_eq!(history.undo(), None);
+
+        // empty transactions are not kept
+        history.begin();
+        history.end();
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,641 @@
+use std::{
+    fs,
+    io::{self, ErrorKind, Read},
+    path::Path,
+};
+
+use bevy::prelude::*;
+use flate2::read::GzDecoder;
+
+use super::block;
+use super::chunk::ChunkMeshData;
+use super::edit::{EditTools, Tool};
+use super::storage;
+use super::world::World;
+
+pub mod nbt;
+pub mod vox;
+
+const SCHEMATIC_MAGIC: &[u8; 4] = b"OWSC";
+const SCHEMATIC_VERSION: u8 = 1;
+
+/// Most blocks a schematic read from a file holds, so a corrupted size cannot exhaust
+/// the memory.
+const MAX_VOLUME: usize = 1 << 24;
+
+/// Most bytes a Sponge schematic decompresses to: the block data of the largest
+/// schematic, at most three bytes a block, and room for the palette and other tags.
+const MAX_SPONGE_SIZE: usize = 3 * MAX_VOLUME + (16 << 20);
+
+/// Extension of schematics imported from the Sponge format instead of our own.
+pub const SPONGE_EXTENSION: &str = "schem";
+
+/// Extension of MagicaVoxel models, imported and exported.
+pub const VOX_EXTENSION: &str = "vox";
+
+/// Box of blocks copied out of the world, to be pasted elsewhere or saved to a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    pub size: IVec3,
+    /// Blocks indexed by `x + z * size.x + y * size.x * size.z`, like Sponge schematics.
+    pub blocks: Vec<u8>,
+}
+
+impl Schematic {
+    /// Schematic of the given size holding only air.
+    pub fn new(size: IVec3) -> Self {
+        let size = size.max(IVec3::ZERO);
+
+        Self {
+            size,
+            blocks: vec![block::AIR; volume(size).expect("schematic too large")],
+        }
+    }
+
+    fn index(&self, position: IVec3) -> usize {
+        (position.x + position.z * self.size.x + position.y * self.size.x * self.size.z) as usize
+    }
+
+    pub fn get(&self, position: IVec3) -> u8 {
+        self.blocks[self.index(position)]
+    }
+
+    pub fn set(&mut self, position: IVec3, block: u8) {
+        let index = self.index(position);
+        self.blocks[index] = block;
+    }
+
+    /// Positions inside the schematic.
+    pub fn positions(&self) -> impl Iterator<Item = IVec3> {
+        let size = self.size;
+
+        (0..size.y).flat_map(move |y| {
+            (0..size.z).flat_map(move |z| (0..size.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Copies the box between two corners, both included. Blocks of chunks that are not
+    /// generated are copied as air. Fails for boxes too large to select.
+    pub fn copy(world: &World, a: IVec3, b: IVec3) -> io::Result<Self> {
+        let min = a.min(b);
+        let size = a.max(b) - min + 1;
+        check_selection(size)?;
+
+        let mut schematic = Self::new(size);
+
+        for position in schematic.positions() {
+            let block = world.get_block(min + position).unwrap_or(block::AIR);
+            schematic.set(position, block);
+        }
+
+        Ok(schematic)
+    }
+
+    /// Writes the blocks with their lowest corner at `origin`, as one transaction of the
+    /// history. Air is only pasted with `include_air`. Returns how many blocks changed.
+    pub fn paste(&self, world: &mut World, origin: IVec3, include_air: bool) -> usize {
+        world.set_blocks(
+            self.positions()
+                .map(|position| (origin + position, self.get(position)))
+                .filter(|(_, block)| include_air || *block != block::AIR),
+        )
+    }
+
+    /// Quarter turn around the vertical axis, clockwise seen from above.
+    pub fn rotated(&self) -> Self {
+        let mut rotated = Self::new(IVec3::new(self.size.z, self.size.y, self.size.x));
+
+        for position in self.positions() {
+            let turned = IVec3::new(self.size.z - 1 - position.z, position.y, position.x);
+            rotated.set(turned, self.get(position));
+        }
+
+        rotated
+    }
+
+    /// Mirror image across the plane normal to `axis`, 0 for x and 2 for z.
+    pub fn mirrored(&self, axis: usize) -> Self {
+        let mut mirrored = Self::new(self.size);
+
+        for position in self.positions() {
+            let mut flipped = position;
+            flipped[axis] = self.size[axis] - 1 - position[axis];
+            mirrored.set(flipped, self.get(position));
+        }
+
+        mirrored
+    }
+
+    /// Lowest corner to paste at so the schematic stands on `target`, centred on it.
+    pub fn paste_origin(&self, target: IVec3) -> IVec3 {
+        target - IVec3::new(self.size.x / 2, 0, self.size.z / 2)
+    }
+
+    /// File layout: magic, version, the size as three `u16`, then the blocks run-length
+    /// encoded like the chunk files. Fails for schematics wider than a `u16`.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        if self.size.cmpgt(IVec3::splat(u16::MAX as i32)).any() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("schematics are at most {} blocks wide", u16::MAX),
+            ));
+        }
+
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(SCHEMATIC_MAGIC);
+        bytes.push(SCHEMATIC_VERSION);
+
+        for axis in 0..3 {
+            bytes.extend_from_slice(&(self.size[axis] as u16).to_le_bytes());
+        }
+
+        storage::write_runs(&mut bytes, &self.blocks);
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 11 || &bytes[..4] != SCHEMATIC_MAGIC {
+            return Err(invalid("not a schematic file"));
+        }
+
+        if bytes[4] != SCHEMATIC_VERSION {
+            return Err(invalid("unsupported schematic version"));
+        }
+
+        let size = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as i32;
+        let size = IVec3::new(size(5), size(7), size(9));
+
+        if !fits(size, MAX_VOLUME) {
+            return Err(invalid("schematic too large"));
+        }
+
+        let mut schematic = Self::new(size);
+        let mut offset = 11;
+
+        storage::read_runs(bytes, &mut offset, &mut schematic.blocks)?;
+
+        if offset != bytes.len() {
+            return Err(invalid("trailing data"));
+        }
+
+        Ok(schematic)
+    }
+
+    /// Reads a schematic of our own format, or a Sponge or MagicaVoxel one by its
+    /// extension.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(SPONGE_EXTENSION) => Self::import_sponge(&bytes),
+            Some(VOX_EXTENSION) => vox::import(&bytes),
+            _ => Self::decode(&bytes),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if path.extension().is_some_and(|ext| ext == SPONGE_EXTENSION) {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Sponge schematics can only be imported",
+            ));
+        }
+
+        let bytes = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(VOX_EXTENSION) => vox::export(self)?,
+            _ => self.encode()?,
+        };
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        storage::write_atomic(path, &bytes)
+    }
+
+    /// Imports a Sponge schematic of version 1 to 3, gzipped or not. Block states are
+    /// mapped to registry blocks by name, see `block_from_state`.
+    pub fn import_sponge(bytes: &[u8]) -> io::Result<Self> {
+        let mut data = vec![];
+
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            // one byte past the limit tells a file that is too large
+            GzDecoder::new(bytes)
+                .take(MAX_SPONGE_SIZE as u64 + 1)
+                .read_to_end(&mut data)?;
+        } else {
+            data.extend_from_slice(bytes);
+        }
+
+        if data.len() > MAX_SPONGE_SIZE {
+            return Err(invalid("schematic too large"));
+        }
+
+        let (_, root) = nbt::read(&data)?;
+
+        // version 3 nests everything in a `Schematic` compound, and the blocks in `Blocks`
+        let schematic = root.get("Schematic").unwrap_or(&root);
+        let blocks = schematic.get("Blocks").unwrap_or(schematic);
+
+        let dimension = |name: &str| {
+            schematic
+                .get(name)
+                .and_then(nbt::Tag::as_int)
+                .map(|value| value as u16 as i32)
+                .ok_or_else(|| invalid("schematic without dimensions"))
+        };
+        let size = IVec3::new(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+
+        let Some(nbt::Tag::Compound(palette)) = blocks.get("Palette") else {
+            return Err(invalid("schematic without palette"));
+        };
+        let Some(nbt::Tag::ByteArray(data)) = blocks.get("BlockData").or(blocks.get("Data")) else {
+            return Err(invalid("schematic without block data"));
+        };
+
+        let mut ids = vec![];
+        for (state, index) in palette {
+            let index = index
+                .as_int()
+                .filter(|index| (0..=u16::MAX as i64).contains(index))
+                .ok_or_else(|| invalid("corrupted palette"))? as usize;
+
+            if ids.len() <= index {
+                ids.resize(index + 1, block::AIR);
+            }
+            ids[index] = block_from_state(state);
+        }
+
+        if !fits(size, MAX_VOLUME.min(data.len())) {
+            return Err(invalid("schematic too large for its block data"));
+        }
+
+        let mut schematic = Self::new(size);
+        let mut data = data.iter();
+
+        for block in schematic.blocks.iter_mut() {
+            let index = read_varint(&mut data)?;
+            *block = *ids
+                .get(index)
+                .ok_or_else(|| invalid("block missing from the palette"))?;
+        }
+
+        Ok(schematic)
+    }
+
+    /// Mesh of the faces between blocks and air, for previews. Vertices are relative to
+    /// the lowest corner.
+    pub fn mesh(&self) -> Mesh {
+        let mut data = ChunkMeshData::default();
+
+        let inside =
+            |position: IVec3| position.cmpge(IVec3::ZERO).all() && position.cmplt(self.size).all();
+
+        for position in self.positions() {
+            let block = self.get(position);
+
+            if block == block::AIR {
+                continue;
+            }
+
+            let color = block::info(block).color;
+
+            for axis in 0..3 {
+                for side in [-1, 1] {
+                    let mut normal = IVec3::ZERO;
+                    normal[axis] = side;
+
+                    let neighbour = position + normal;
+                    if inside(neighbour) && self.get(neighbour) != block::AIR {
+                        continue;
+                    }
+
+                    let (u, v) = (Vec3::AXES[(axis + 1) % 3], Vec3::AXES[(axis + 2) % 3]);
+                    let mut base = position.as_vec3();
+                    if side > 0 {
+                        base += Vec3::AXES[axis];
+                    }
+                    let mut corners = [base, base + u, base + u + v, base + v];
+
+                    // counterclockwise seen from outside
+                    if side < 0 {
+                        corners.reverse();
+                    }
+
+                    let first = data.positions.len() as u32;
+                    for corner in corners {
+                        data.positions.push(corner.to_array());
+                        data.normals.push(normal.as_vec3().to_array());
+                        data.colors.push(color);
+                        data.uvs.push([0.0, 0.0]);
+                    }
+                    data.indices
+                        .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+                }
+            }
+        }
+
+        data.build().0
+    }
+}
+
+/// Blocks of a schematic of `size`, or `None` when they cannot be counted in a usize.
+fn volume(size: IVec3) -> Option<usize> {
+    let [x, y, z] = size.max(IVec3::ZERO).to_array().map(|axis| axis as usize);
+    x.checked_mul(y)?.checked_mul(z)
+}
+
+/// Whether a schematic of `size` holds at most `limit` blocks.
+fn fits(size: IVec3, limit: usize) -> bool {
+    volume(size).is_some_and(|volume| volume <= limit)
+}
+
+/// Fails for a selection of `size` holding more than `MAX_VOLUME` blocks, which would
+/// take too much memory to copy or edit at once.
+pub(super) fn check_selection(size: IVec3) -> io::Result<()> {
+    if fits(size, MAX_VOLUME) {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("selections hold at most {} blocks", MAX_VOLUME),
+    ))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads an unsigned LEB128 integer, as Sponge block data is stored.
+fn read_varint<'a>(bytes: &mut impl Iterator<Item = &'a u8>) -> io::Result<usize> {
+    let mut value = 0;
+
+    for shift in (0..32).step_by(7) {
+        let byte = *bytes
+            .next()
+            .ok_or_else(|| invalid("truncated block data"))?;
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid("corrupted block data"))
+}
+
+/// Minecraft blocks standing for a registry block, by name without the namespace.
+const BLOCK_ALIASES: [(&str, u8); 6] = [
+    ("air", block::AIR),
+    ("cave_air", block::AIR),
+    ("void_air", block::AIR),
+    ("structure_void", block::AIR),
+    ("water", block::WATER),
+    ("grass_block", block::GRASS),
+];
+
+/// Registry block for a Minecraft block state like `minecraft:oak_stairs[facing=east]`:
+/// the block its name is an alias of, or grass, the registry's plain solid block.
+pub fn block_from_state(state: &str) -> u8 {
+    let name = state.split('[').next().unwrap_or(state);
+    let name = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+
+    BLOCK_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(block::GRASS, |(_, block)| *block)
+}
+
+/// Schematic copied from the world, and how it is pasted back.
+#[derive(Resource, Debug)]
+pub struct Clipboard {
+    pub schematic: Option<Schematic>,
+    /// Paste the air of the schematic too, clearing what was there.
+    pub include_air: bool,
+    /// File the schematic is saved to and loaded from.
+    pub path: String,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            schematic: None,
+            include_air: false,
+            path: "schematics/selection.owsc".to_string(),
+        }
+    }
+}
+
+/// Translucent copy of the clipboard shown where the paste tool would paste it.
+#[derive(Component)]
+pub struct PastePreview;
+
+pub fn preview_paste(
+    mut commands: Commands,
+    tools: Res<EditTools>,
+    clipboard: Res<Clipboard>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut preview_query: Query<(Entity, &mut Transform, &mut Visibility), With<PastePreview>>,
+) {
+    if clipboard.is_changed() {
+        for (entity, _, _) in preview_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        if let Some(schematic) = &clipboard.schematic {
+            commands.spawn((
+                PbrBundle {
+                    mesh: meshes.add(schematic.mesh()),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::rgba(1.0, 1.0, 1.0, 0.4),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..default()
+                    }),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                PastePreview,
+            ));
+        }
+
+        return;
+    }
+
+    for (_, mut transform, mut visibility) in preview_query.iter_mut() {
+        match (tools.tool, tools.aim, &clipboard.schematic) {
+            (Tool::Paste, Some(aim), Some(schematic)) => {
+                let origin = schematic.paste_origin(aim.position + aim.normal);
+
+                transform.translation = origin.as_vec3();
+                *visibility = Visibility::Visible;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+    use crate::voxel::chunk::Chunk;
+
+    /// L shaped schematic, two blocks along x and one more up at the origin.
+    fn corner() -> Schematic {
+        let mut schematic = Schematic::new(IVec3::new(2, 2, 3));
+        schematic.set(IVec3::ZERO, block::GRASS);
+        schematic.set(IVec3::X, block::WATER);
+        schematic.set(IVec3::Y, block::GRASS);
+        schematic
+    }
+
+    #[test]
+    fn rotation_and_mirroring() {
+        let schematic = corner();
+        let rotated = schematic.rotated();
+
+        assert_eq!(rotated.size, IVec3::new(3, 2, 2));
+        assert_eq!(rotated.get(IVec3::new(2, 0, 0)), block::GRASS);
+        assert_eq!(rotated.get(IVec3::new(2, 0, 1)), block::WATER);
+        assert_eq!(rotated.get(IVec3::new(2, 1, 0)), block::GRASS);
+
+        assert_eq!(rotated.rotated().rotated().rotated(), schematic);
+
+        let mirrored = schematic.mirrored(0);
+        assert_eq!(mirrored.get(IVec3::new(1, 0, 0)), block::GRASS);
+        assert_eq!(mirrored.get(IVec3::new(0, 0, 0)), block::WATER);
+        assert_eq!(mirrored.mirrored(0), schematic);
+
+        assert_eq!(schematic.mirrored(2).get(IVec3::new(0, 1, 2)), block::GRASS);
+    }
+
+    #[test]
+    fn copies_and_pastes_as_one_undo() {
+        let mut world = World::new();
+        world.insert_loaded_chunk(Chunk::new(IVec3::ZERO));
+
+        corner().paste(&mut world, IVec3::new(10, 5, 10), false);
+        let copy = Schematic::copy(&world, IVec3::new(11, 6, 12), IVec3::new(10, 5, 10)).unwrap();
+        assert_eq!(copy, corner());
+
+        // air is left alone unless asked for
+        world.set_block(IVec3::new(31, 6, 30), block::WATER);
+        assert_eq!(copy.paste(&mut world, IVec3::new(30, 5, 30), false), 3);
+        assert_eq!(world.get_block(IVec3::new(31, 6, 30)), Some(block::WATER));
+        copy.paste(&mut world, IVec3::new(30, 5, 30), true);
+        assert_eq!(world.get_block(IVec3::new(31, 6, 30)), Some(block::AIR));
+
+        world.undo();
+        world.undo();
+        assert_eq!(world.get_block(IVec3::new(30, 5, 30)), Some(block::AIR));
+        assert_eq!(world.get_block(IVec3::new(31, 6, 30)), Some(block::WATER));
+
+        assert_eq!(copy.paste_origin(IVec3::new(5, 5, 5)), IVec3::new(4, 5, 4));
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let schematic = corner().rotated();
+        let bytes = schematic.encode().unwrap();
+
+        assert_eq!(Schematic::decode(&bytes).unwrap(), schematic);
+        assert!(Schematic::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Schematic::decode(b"OWCH\x01").is_err());
+    }
+
+    #[test]
+    fn oversized_selections_are_refused() {
+        let world = World::new();
+
+        let far = IVec3::new(100_000, 63, 100_000);
+        assert!(Schematic::copy(&world, IVec3::ZERO, far).is_err());
+        assert!(Schematic::copy(&world, -far, far).is_err());
+
+        // copied, but too wide for the file's sizes
+        let wide = Schematic::copy(&world, IVec3::ZERO, IVec3::new(70_000, 0, 0)).unwrap();
+        assert!(wide.encode().is_err());
+    }
+
+    fn named(bytes: &mut Vec<u8>, kind: u8, name: &str) {
+        bytes.push(kind);
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    /// Gzipped Sponge schematic of version 2, 2 by 1 by 2, with a block of index 200 to
+    /// need a two byte varint.
+    fn sponge() -> Vec<u8> {
+        let mut nbt = vec![];
+        named(&mut nbt, 10, "Schematic");
+
+        named(&mut nbt, 3, "Version");
+        nbt.extend_from_slice(&2_i32.to_be_bytes());
+        for (name, size) in [("Width", 2_i16), ("Height", 1), ("Length", 2)] {
+            named(&mut nbt, 2, name);
+            nbt.extend_from_slice(&size.to_be_bytes());
+        }
+
+        named(&mut nbt, 10, "Palette");
+        for (state, index) in [
+            ("minecraft:air", 0_i32),
+            ("minecraft:water[level=0]", 1),
+            ("minecraft:stone", 200),
+        ] {
+            named(&mut nbt, 3, state);
+            nbt.extend_from_slice(&index.to_be_bytes());
+        }
+        nbt.push(0);
+
+        let data = [1, 0, 0xc8, 0x01, 1];
+        named(&mut nbt, 7, "BlockData");
+        nbt.extend_from_slice(&(data.len() as i32).to_be_bytes());
+        nbt.extend_from_slice(&data);
+        nbt.push(0);
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&nbt).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn imports_sponge_schematics() {
+        let schematic = Schematic::import_sponge(&sponge()).unwrap();
+
+        assert_eq!(schematic.size, IVec3::new(2, 1, 2));
+        assert_eq!(
+            schematic.blocks,
+            vec![block::WATER, block::AIR, block::GRASS, block::WATER]
+        );
+
+        let bytes = sponge();
+        assert!(Schematic::import_sponge(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn block_states_map_to_the_registry() {
+        let states: HashMap<&str, u8> = HashMap::from([
+            ("minecraft:cave_air", block::AIR),
+            ("minecraft:water[level=3]", block::WATER),
+            ("minecraft:grass_block[snowy=false]", block::GRASS),
+            ("minecraft:oak_planks", block::GRASS),
+            ("minecraft:oak_stairs[facing=east]", block::GRASS),
+            ("minecraft:water_cauldron", block::GRASS),
+            ("minecraft:seagrass", block::GRASS),
+        ]);
+
+        for (state, expected) in states {
+            assert_eq!(block_from_state(state), expected, "{}", state);
+        }
+    }
+}
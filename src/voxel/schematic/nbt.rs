@@ -0,0 +1,182 @@
+use std::{collections::HashMap, io};
+
+use super::invalid;
+
+/// Deepest nesting of lists and compounds read, so a malicious file cannot overflow the
+/// stack.
+const MAX_DEPTH: usize = 512;
+
+/// Value of the Named Binary Tag format Minecraft tools store schematics in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    /// Integer value of any integer tag.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the named root tag of uncompressed NBT data.
+pub fn read(bytes: &[u8]) -> io::Result<(String, Tag)> {
+    let mut reader = Reader { bytes, offset: 0 };
+
+    let kind = reader.u8()?;
+    let name = reader.string()?;
+    let tag = reader.payload(kind, 0)?;
+
+    Ok((name, tag))
+}
+
+/// Big-endian cursor over the data.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + N)
+            .ok_or_else(|| invalid("truncated NBT"))?;
+
+        self.offset += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    /// Length of an array or list, which must fit in what is left of the data.
+    fn len(&mut self, element_size: usize) -> io::Result<usize> {
+        let len = self.i32()?;
+
+        if len < 0 || len as usize * element_size > self.bytes.len() - self.offset {
+            return Err(invalid("corrupted NBT length"));
+        }
+
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| invalid("truncated NBT"))?;
+
+        self.offset += len;
+
+        // modified UTF-8 only differs for nul and supplementary characters, which block
+        // names never hold
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn payload(&mut self, kind: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("NBT nested too deep"));
+        }
+
+        Ok(match kind {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let len = self.len(1)?;
+                let bytes = self.bytes[self.offset..self.offset + len].to_vec();
+                self.offset += len;
+                Tag::ByteArray(bytes)
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let kind = self.u8()?;
+                let len = self.len(1)?;
+
+                if kind == 0 && len > 0 {
+                    return Err(invalid("list of end tags"));
+                }
+
+                let mut tags = Vec::with_capacity(len);
+                for _ in 0..len {
+                    tags.push(self.payload(kind, depth + 1)?);
+                }
+                Tag::List(tags)
+            }
+            10 => {
+                let mut tags = HashMap::new();
+
+                loop {
+                    let kind = self.u8()?;
+
+                    if kind == 0 {
+                        break;
+                    }
+
+                    let name = self.string()?;
+                    tags.insert(name, self.payload(kind, depth + 1)?);
+                }
+                Tag::Compound(tags)
+            }
+            11 => {
+                let len = self.len(4)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(self.i32()?);
+                }
+                Tag::IntArray(values)
+            }
+            12 => {
+                let len = self.len(8)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(self.i64()?);
+                }
+                Tag::LongArray(values)
+            }
+            _ => return Err(invalid("unknown NBT tag")),
+        })
+    }
+}
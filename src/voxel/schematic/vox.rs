@@ -0,0 +1,692 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+};
+
+use bevy::prelude::*;
+
+use super::{invalid, Schematic};
+use crate::voxel::block;
+
+const VOX_MAGIC: &[u8; 4] = b"VOX ";
+const VOX_VERSION: i32 = 150;
+
+/// Largest model MagicaVoxel opens, along each axis.
+pub const MAX_MODEL_SIZE: i32 = 256;
+
+/// Most scene nodes visited while placing the models. Groups listing the same node more
+/// than once repeat it, so a small file could otherwise hold an endless scene.
+const MAX_SCENE_VISITS: usize = 1 << 20;
+
+/// RGBA colors of a MagicaVoxel palette, indexed by color index. Index 0 is never used
+/// by voxels.
+pub type Palette = [[u8; 4]; 256];
+
+/// Palette of files without one: a 6 by 6 by 6 color cube without black, then ramps of
+/// red, green, blue and grey.
+pub fn default_palette() -> Palette {
+    const STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+
+    for r in STEPS {
+        for g in STEPS {
+            for b in STEPS {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    for channel in 0..4 {
+        for value in RAMP {
+            palette[index] = match channel {
+                3 => [value, value, value, 0xff],
+                _ => {
+                    let mut color = [0, 0, 0, 0xff];
+                    color[channel] = value;
+                    color
+                }
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+/// Color of a registry block as palette bytes.
+fn block_color(block: u8) -> [u8; 4] {
+    block::info(block).color.map(|c| (c * 255.0).round() as u8)
+}
+
+/// Registry block of every palette color. A block id used as an index holding that
+/// block's color is the block, as `export` writes them, so blocks of close colors stay
+/// apart. Any other color is the block of the nearest color.
+pub fn palette_blocks(palette: &Palette) -> [u8; 256] {
+    let blocks: Vec<(u8, Vec3)> = (0..block::BLOCKS.len() as u8)
+        .filter(|block| *block != block::AIR)
+        .map(|block| {
+            let [r, g, b, _] = block_color(block);
+            (block, Vec3::new(r as f32, g as f32, b as f32))
+        })
+        .collect();
+
+    let mut mapping = [block::AIR; 256];
+
+    for (index, [r, g, b, _]) in palette.iter().enumerate().skip(1) {
+        if index < block::BLOCKS.len() && block_color(index as u8)[..3] == [*r, *g, *b] {
+            mapping[index] = index as u8;
+            continue;
+        }
+
+        let color = Vec3::new(*r as f32, *g as f32, *b as f32);
+
+        mapping[index] = blocks
+            .iter()
+            .min_by(|(_, a), (_, b)| a.distance(color).total_cmp(&b.distance(color)))
+            .map_or(block::GRASS, |(block, _)| *block);
+    }
+
+    mapping
+}
+
+/// Model of the file: its size and voxels as `(x, y, z, color index)`.
+struct Model {
+    size: IVec3,
+    voxels: Vec<[u8; 4]>,
+}
+
+/// Chunks of the scene graph placing the models.
+enum Node {
+    Transform { child: i32, translation: IVec3 },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+/// Imports a MagicaVoxel file, every model at the place the scene graph puts it.
+/// Rotations of the scene are not applied. Colors are mapped with `palette_blocks`, and
+/// MagicaVoxel's z up axis becomes y.
+pub fn import(bytes: &[u8]) -> io::Result<Schematic> {
+    if bytes.len() < 8 || &bytes[..4] != VOX_MAGIC {
+        return Err(invalid("not a MagicaVoxel file"));
+    }
+
+    let mut reader = Reader { bytes, offset: 8 };
+    let (id, content) = reader.chunk()?;
+
+    if &id != b"MAIN" || !content.is_empty() {
+        return Err(invalid("MagicaVoxel file without main chunk"));
+    }
+
+    let mut models = vec![];
+    let mut size = None;
+    let mut palette = default_palette();
+    let mut nodes = HashMap::new();
+
+    while reader.offset < bytes.len() {
+        let (id, content) = reader.chunk()?;
+        let mut content = Reader {
+            bytes: content,
+            offset: 0,
+        };
+
+        match &id {
+            b"SIZE" => {
+                let model_size = IVec3::new(content.i32()?, content.i32()?, content.i32()?);
+
+                if model_size.cmple(IVec3::ZERO).any()
+                    || model_size.cmpgt(IVec3::splat(MAX_MODEL_SIZE)).any()
+                {
+                    return Err(invalid("corrupted model size"));
+                }
+
+                size = Some(model_size);
+            }
+            b"XYZI" => {
+                let size = size.take().ok_or_else(|| invalid("voxels without size"))?;
+                let count = content.len(4)?;
+                let mut voxels = Vec::with_capacity(count);
+
+                for _ in 0..count {
+                    voxels.push(content.take::<4>()?);
+                }
+
+                models.push(Model { size, voxels });
+            }
+            b"RGBA" => {
+                for color in palette.iter_mut().skip(1) {
+                    *color = content.take::<4>()?;
+                }
+            }
+            b"nTRN" => {
+                let id = content.i32()?;
+                content.dict()?;
+                let child = content.i32()?;
+                let _reserved = content.i32()?;
+                let _layer = content.i32()?;
+
+                let frames = content.len(4)?;
+                let mut translation = IVec3::ZERO;
+
+                for frame in 0..frames {
+                    let attributes = content.dict()?;
+
+                    if let Some(t) = attributes.get("_t").filter(|_| frame == 0) {
+                        // far beyond any real scene, so adding them up cannot overflow
+                        let values: Vec<i32> = t
+                            .split_whitespace()
+                            .filter_map(|v| v.parse().ok())
+                            .filter(|v: &i32| v.abs() < 1 << 20)
+                            .collect();
+
+                        if let [x, y, z] = values[..] {
+                            translation = IVec3::new(x, y, z);
+                        }
+                    }
+                }
+
+                nodes.insert(id, Node::Transform { child, translation });
+            }
+            b"nGRP" => {
+                let id = content.i32()?;
+                content.dict()?;
+
+                let count = content.len(4)?;
+                let mut children = Vec::with_capacity(count);
+                for _ in 0..count {
+                    children.push(content.i32()?);
+                }
+
+                nodes.insert(id, Node::Group { children });
+            }
+            b"nSHP" => {
+                let id = content.i32()?;
+                content.dict()?;
+
+                let count = content.len(4)?;
+                let mut shape_models = Vec::with_capacity(count);
+                for _ in 0..count {
+                    shape_models.push(content.i32()?);
+                    content.dict()?;
+                }
+
+                nodes.insert(
+                    id,
+                    Node::Shape {
+                        models: shape_models,
+                    },
+                );
+            }
+            // materials, layers, cameras and notes say nothing about the blocks
+            _ => {}
+        }
+    }
+
+    if models.is_empty() {
+        return Err(invalid("MagicaVoxel file without models"));
+    }
+
+    // lowest corner of every model, in MagicaVoxel coordinates
+    let mut placements = vec![];
+    if nodes.contains_key(&0) {
+        let mut visits = 0;
+        place(
+            &nodes,
+            0,
+            IVec3::ZERO,
+            &models,
+            &mut placements,
+            &mut visits,
+            0,
+        )?;
+    } else {
+        placements.push((0, IVec3::ZERO));
+    }
+
+    let min = placements
+        .iter()
+        .map(|(_, corner)| *corner)
+        .reduce(IVec3::min)
+        .unwrap_or(IVec3::ZERO);
+    let max = placements
+        .iter()
+        .map(|(model, corner)| *corner + models[*model].size)
+        .reduce(IVec3::max)
+        .unwrap_or(IVec3::ZERO);
+
+    // y is flipped with the axes swapped, so models are not mirrored
+    let extent = max - min;
+    let size = IVec3::new(extent.x, extent.z, extent.y);
+
+    if !super::fits(size, super::MAX_VOLUME) {
+        return Err(invalid("MagicaVoxel scene too large"));
+    }
+
+    let mut schematic = Schematic::new(size);
+
+    let blocks = palette_blocks(&palette);
+
+    for (model, corner) in placements {
+        let model = &models[model];
+
+        for [x, y, z, color] in model.voxels.iter().copied() {
+            let voxel = IVec3::new(x as i32, y as i32, z as i32);
+
+            if voxel.cmpge(model.size).any() || color == 0 {
+                continue;
+            }
+
+            let position = corner - min + voxel;
+            schematic.set(
+                IVec3::new(position.x, position.z, extent.y - 1 - position.y),
+                blocks[color as usize],
+            );
+        }
+    }
+
+    Ok(schematic)
+}
+
+/// Adds the lowest corner of every model under `node` to `placements`, counting the nodes
+/// visited in `visits`.
+fn place(
+    nodes: &HashMap<i32, Node>,
+    node: i32,
+    translation: IVec3,
+    models: &[Model],
+    placements: &mut Vec<(usize, IVec3)>,
+    visits: &mut usize,
+    depth: usize,
+) -> io::Result<()> {
+    // deeper than any real scene, and stops cycles
+    if depth > 64 {
+        return Err(invalid("corrupted MagicaVoxel scene"));
+    }
+
+    *visits += 1;
+
+    if *visits > MAX_SCENE_VISITS || placements.len() > super::MAX_VOLUME {
+        return Err(invalid("MagicaVoxel scene too large"));
+    }
+
+    match nodes.get(&node) {
+        Some(Node::Transform {
+            child,
+            translation: offset,
+        }) => {
+            place(
+                nodes,
+                *child,
+                translation + *offset,
+                models,
+                placements,
+                visits,
+                depth + 1,
+            )?;
+        }
+        Some(Node::Group { children }) => {
+            for child in children {
+                place(
+                    nodes,
+                    *child,
+                    translation,
+                    models,
+                    placements,
+                    visits,
+                    depth + 1,
+                )?;
+            }
+        }
+        Some(Node::Shape {
+            models: shape_models,
+        }) => {
+            for model in shape_models {
+                let size = models
+                    .get(*model as usize)
+                    .ok_or_else(|| invalid("shape of a missing model"))?
+                    .size;
+
+                // translations are of the model's center
+                placements.push((*model as usize, translation - size / 2));
+            }
+        }
+        None => return Err(invalid("missing MagicaVoxel scene node")),
+    }
+
+    Ok(())
+}
+
+/// Exports as a MagicaVoxel file of one model, with a palette of the registry colors.
+pub fn export(schematic: &Schematic) -> io::Result<Vec<u8>> {
+    let size = schematic.size;
+
+    if size.cmpgt(IVec3::splat(MAX_MODEL_SIZE)).any() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "MagicaVoxel models are at most {} blocks wide",
+                MAX_MODEL_SIZE
+            ),
+        ));
+    }
+
+    // block ids become color indices, which `palette_blocks` reads back as the same blocks
+    let mut palette = [[0x80, 0x80, 0x80, 0xff]; 256];
+    for (block, color) in palette
+        .iter_mut()
+        .enumerate()
+        .take(block::BLOCKS.len())
+        .skip(1)
+    {
+        *color = block_color(block as u8);
+    }
+
+    let mut voxels = vec![];
+    for position in schematic.positions() {
+        let block = schematic.get(position);
+
+        if block != block::AIR {
+            voxels.extend([
+                position.x as u8,
+                (size.z - 1 - position.z) as u8,
+                position.y as u8,
+                block,
+            ]);
+        }
+    }
+
+    let mut children = vec![];
+
+    let mut model_size = vec![];
+    for value in [size.x, size.z, size.y] {
+        model_size.extend(value.to_le_bytes());
+    }
+    write_chunk(&mut children, b"SIZE", &model_size, &[]);
+
+    let mut xyzi = ((voxels.len() / 4) as i32).to_le_bytes().to_vec();
+    xyzi.extend(voxels);
+    write_chunk(&mut children, b"XYZI", &xyzi, &[]);
+
+    let rgba: Vec<u8> = palette[1..]
+        .iter()
+        .chain([&[0; 4]])
+        .flatten()
+        .copied()
+        .collect();
+    write_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+    let mut bytes = VOX_MAGIC.to_vec();
+    bytes.extend(VOX_VERSION.to_le_bytes());
+    write_chunk(&mut bytes, b"MAIN", &[], &children);
+
+    Ok(bytes)
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend(id);
+    bytes.extend((content.len() as i32).to_le_bytes());
+    bytes.extend((children.len() as i32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+}
+
+/// Little-endian cursor over the file or a chunk of it.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.slice(N)?.try_into().unwrap())
+    }
+
+    fn slice(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| invalid("truncated MagicaVoxel file"))?;
+
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    /// Count of elements, which must fit in what is left.
+    fn len(&mut self, element_size: usize) -> io::Result<usize> {
+        let len = self.i32()?;
+
+        if len < 0 || len as usize * element_size > self.bytes.len() - self.offset {
+            return Err(invalid("corrupted MagicaVoxel count"));
+        }
+
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.len(1)?;
+        Ok(String::from_utf8_lossy(self.slice(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        let count = self.len(8)?;
+        let mut dict = HashMap::with_capacity(count);
+
+        for _ in 0..count {
+            let key = self.string()?;
+            dict.insert(key, self.string()?);
+        }
+
+        Ok(dict)
+    }
+
+    /// Next chunk: its id and content. Only the main chunk has children, which are read as
+    /// the chunks following it, so the cursor stops at their start.
+    fn chunk(&mut self) -> io::Result<([u8; 4], &'a [u8])> {
+        let id = self.take::<4>()?;
+        let content = self.len(1)?;
+        let children = self.i32()?;
+
+        if children < 0 {
+            return Err(invalid("corrupted MagicaVoxel chunk"));
+        }
+
+        Ok((id, self.slice(content)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(bytes: &mut Vec<u8>, entries: &[(&str, &str)]) {
+        bytes.extend((entries.len() as i32).to_le_bytes());
+        for (key, value) in entries {
+            for text in [key, value] {
+                bytes.extend((text.len() as i32).to_le_bytes());
+                bytes.extend(text.as_bytes());
+            }
+        }
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    /// Transform node moving its child by `translation`.
+    fn transform(bytes: &mut Vec<u8>, node: i32, child: i32, translation: &str) {
+        let mut transform = ints(&[node]);
+        dict(&mut transform, &[]);
+        transform.extend(ints(&[child, -1, 0, 1]));
+        dict(&mut transform, &[("_t", translation)]);
+        write_chunk(bytes, b"nTRN", &transform, &[]);
+    }
+
+    /// Shape node showing `model`.
+    fn shape(bytes: &mut Vec<u8>, node: i32, model: i32) {
+        let mut shape = ints(&[node]);
+        dict(&mut shape, &[]);
+        shape.extend(ints(&[1, model]));
+        dict(&mut shape, &[]);
+        write_chunk(bytes, b"nSHP", &shape, &[]);
+    }
+
+    /// Model of a single voxel of palette index `color`.
+    fn model(bytes: &mut Vec<u8>, color: u8) {
+        write_chunk(bytes, b"SIZE", &ints(&[1, 1, 1]), &[]);
+        let mut xyzi = ints(&[1]);
+        xyzi.extend([0, 0, 0, color]);
+        write_chunk(bytes, b"XYZI", &xyzi, &[]);
+    }
+
+    /// Group node holding `children`.
+    fn group(bytes: &mut Vec<u8>, node: i32, children: &[i32]) {
+        let mut group = ints(&[node]);
+        dict(&mut group, &[]);
+        group.extend(ints(&[children.len() as i32]));
+        group.extend(ints(children));
+        write_chunk(bytes, b"nGRP", &group, &[]);
+    }
+
+    /// Root of the scene, node 0, over the group at node 1 holding `children`.
+    fn root_group(bytes: &mut Vec<u8>, children: &[i32]) {
+        transform(bytes, 0, 1, "0 0 0");
+        group(bytes, 1, children);
+    }
+
+    /// File of the main chunk holding `children`.
+    fn file(children: &[u8]) -> Vec<u8> {
+        let mut bytes = VOX_MAGIC.to_vec();
+        bytes.extend(ints(&[VOX_VERSION]));
+        write_chunk(&mut bytes, b"MAIN", &[], children);
+        bytes
+    }
+
+    #[test]
+    fn default_palette_matches_magicavoxel() {
+        let palette = default_palette();
+
+        assert_eq!(palette[1], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(palette[2], [0xff, 0xff, 0xcc, 0xff]);
+        assert_eq!(palette[215], [0x00, 0x00, 0x33, 0xff]);
+        assert_eq!(palette[216], [0xee, 0x00, 0x00, 0xff]);
+        assert_eq!(palette[255], [0x11, 0x11, 0x11, 0xff]);
+    }
+
+    #[test]
+    fn colors_map_to_the_nearest_block() {
+        let mut palette = [[0; 4]; 256];
+        palette[1] = block_color(block::WATER);
+        palette[2] = [0x00, 0x00, 0xff, 0xff];
+        palette[3] = [0x40, 0xff, 0x40, 0xff];
+
+        let blocks = palette_blocks(&palette);
+
+        assert_eq!(blocks[0], block::AIR);
+        assert_eq!(blocks[1], block::WATER);
+        assert_eq!(blocks[2], block::WATER);
+        assert_eq!(blocks[3], block::GRASS);
+    }
+
+    #[test]
+    fn block_indices_of_their_color_map_to_the_block() {
+        let mut palette = [[0; 4]; 256];
+        palette[block::WATER as usize] = block_color(block::WATER);
+        palette[block::GRASS as usize] = block_color(block::GRASS);
+
+        let blocks = palette_blocks(&palette);
+        assert_eq!(blocks[block::WATER as usize], block::WATER);
+        assert_eq!(blocks[block::GRASS as usize], block::GRASS);
+
+        // the index of water holding the color of grass is grass
+        palette[block::WATER as usize] = block_color(block::GRASS);
+        assert_eq!(
+            palette_blocks(&palette)[block::WATER as usize],
+            block::GRASS
+        );
+    }
+
+    #[test]
+    fn exports_round_trip() {
+        let mut schematic = Schematic::new(IVec3::new(3, 2, 4));
+        schematic.set(IVec3::new(0, 0, 0), block::GRASS);
+        schematic.set(IVec3::new(2, 1, 3), block::WATER);
+        schematic.set(IVec3::new(1, 0, 3), block::GRASS);
+
+        let bytes = export(&schematic).unwrap();
+        assert_eq!(import(&bytes).unwrap(), schematic);
+
+        assert!(import(&bytes[..bytes.len() - 1]).is_err());
+        assert!(export(&Schematic::new(IVec3::new(257, 1, 1))).is_err());
+    }
+
+    #[test]
+    fn scene_graph_places_the_models() {
+        // two single voxel models, the second 3 to the right of the first and 1 higher
+        let mut children = vec![];
+        model(&mut children, 1);
+        model(&mut children, 255);
+        root_group(&mut children, &[2, 4]);
+
+        for (node, model, translation) in [(2, 0, "0 0 0"), (4, 1, "3 0 1")] {
+            transform(&mut children, node, node + 1, translation);
+            shape(&mut children, node + 1, model);
+        }
+
+        let schematic = import(&file(&children)).unwrap();
+
+        assert_eq!(schematic.size, IVec3::new(4, 2, 1));
+        // white of the default palette is nearest to grass, dark grey to water
+        assert_eq!(schematic.get(IVec3::new(0, 0, 0)), block::GRASS);
+        assert_eq!(schematic.get(IVec3::new(3, 1, 0)), block::WATER);
+    }
+
+    #[test]
+    fn scenes_spread_too_far_apart_are_rejected() {
+        let mut children = vec![];
+        model(&mut children, 1);
+        root_group(&mut children, &[2, 4]);
+
+        transform(&mut children, 2, 3, "0 0 0");
+        shape(&mut children, 3, 0);
+
+        // the other copy is moved away by a chain of transforms, so far that the volume
+        // between them does not fit in a usize
+        let depth = 40;
+        for node in 4..4 + depth {
+            transform(&mut children, node, node + 1, "1000000 1000000 1000000");
+        }
+        shape(&mut children, 4 + depth, 0);
+
+        let error = import(&file(&children)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn scenes_repeating_shared_nodes_are_rejected() {
+        let mut children = vec![];
+        model(&mut children, 1);
+        root_group(&mut children, &[2]);
+
+        // every group lists the next one twice, doubling the scene at each level
+        let levels = 60;
+        for node in 2..2 + levels {
+            group(&mut children, node, &[node + 1, node + 1]);
+        }
+        shape(&mut children, 2 + levels, 0);
+
+        let error = import(&file(&children)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::chunk::*;
+use crate::skybox::CubemapSource;
+
+const CHUNK_MAGIC: &[u8; 4] = b"OWCH";
+const CHUNK_VERSION: u8 = 1;
+
+/// Settings a saved world was generated with, stored as `world.json` in its folder.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldInfo {
+    pub seed: u32,
+    pub meshing: MeshingMode,
+    /// Cubemap shown instead of the procedural sky.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sky: Option<CubemapSource>,
+}
+
+pub fn read_info(dir: &Path) -> io::Result<Option<WorldInfo>> {
+    read_json(dir, "world.json")
+}
+
+pub fn write_info(dir: &Path, info: &WorldInfo) -> io::Result<()> {
+    write_json(dir, "world.json", info)
+}
+
+/// Reads a JSON file of the save folder, or `None` when there is no such file.
+pub fn read_json<T: DeserializeOwned>(dir: &Path, name: &str) -> io::Result<Option<T>> {
+    match fs::read(dir.join(name)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+pub fn write_json<T: Serialize>(dir: &Path, name: &str, value: &T) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(value)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+
+    write_atomic(&dir.join(name), &json)
+}
+
+pub fn chunk_path(dir: &Path, position: IVec3) -> PathBuf {
+    dir.join("chunks").join(format!(
+        "{}.{}.{}.chunk",
+        position.x, position.y, position.z
+    ))
+}
+
+pub fn save_chunk(dir: &Path, chunk: &Chunk) -> io::Result<()> {
+    let path = chunk_path(dir, chunk.position);
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    write_atomic(&path, &encode_chunk(chunk))
+}
+
+/// Loads a saved chunk, or `None` when it was never saved.
+pub fn load_chunk(dir: &Path, position: IVec3) -> io::Result<Option<Chunk>> {
+    match fs::read(chunk_path(dir, position)) {
+        Ok(bytes) => decode_chunk(&bytes, position).map(Some),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Writes through a temporary file so an interrupted write never leaves a truncated file.
+pub(super) fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Chunk file layout: magic, version, a flag telling whether a density field follows, the
+/// blocks run-length encoded as `(u16 count, u8 block)` pairs, then the raw density samples.
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let blocks = chunk.blocks.as_ref().read();
+    let density = chunk.density.as_ref().read();
+
+    let mut bytes = Vec::with_capacity(1024);
+    bytes.extend_from_slice(CHUNK_MAGIC);
+    bytes.push(CHUNK_VERSION);
+    bytes.push(!density.is_empty() as u8);
+
+    write_runs(&mut bytes, &blocks);
+
+    for sample in density.iter() {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Appends blocks run-length encoded as `(u16 count, u8 block)` pairs.
+pub(super) fn write_runs(bytes: &mut Vec<u8>, blocks: &[u8]) {
+    let mut runs = blocks.iter().peekable();
+
+    while let Some(&block) = runs.next() {
+        let mut count: u16 = 1;
+
+        while count < u16::MAX && runs.peek() == Some(&&block) {
+            runs.next();
+            count += 1;
+        }
+
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.push(block);
+    }
+}
+
+/// Fills `blocks` from runs written by `write_runs` starting at `offset`, which is moved
+/// past them.
+pub(super) fn read_runs(bytes: &[u8], offset: &mut usize, blocks: &mut [u8]) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut filled = 0;
+
+    while filled < blocks.len() {
+        let run = bytes
+            .get(*offset..*offset + 3)
+            .ok_or_else(|| invalid("truncated blocks"))?;
+        let count = u16::from_le_bytes([run[0], run[1]]) as usize;
+
+        if count == 0 || filled + count > blocks.len() {
+            return Err(invalid("corrupted blocks"));
+        }
+
+        blocks[filled..filled + count].fill(run[2]);
+        filled += count;
+        *offset += 3;
+    }
+
+    Ok(())
+}
+
+fn decode_chunk(bytes: &[u8], position: IVec3) -> io::Result<Chunk> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+
+    if bytes.len() < 6 || &bytes[..4] != CHUNK_MAGIC {
+        return Err(invalid("not a chunk file"));
+    }
+
+    if bytes[4] != CHUNK_VERSION {
+        return Err(invalid("unsupported chunk version"));
+    }
+
+    let has_density = bytes[5] != 0;
+    let mut chunk = if has_density {
+        Chunk::with_density(position)
+    } else {
+        Chunk::new(position)
+    };
+
+    let mut offset = 6;
+
+    read_runs(bytes, &mut offset, &mut chunk.blocks.as_ref().write())?;
+
+    if has_density {
+        let mut density = chunk.density.as_ref().write();
+        let samples = bytes
+            .get(offset..offset + density.len() * 4)
+            .ok_or_else(|| invalid("truncated density"))?;
+
+        for (sample, value) in density.iter_mut().zip(samples.chunks_exact(4)) {
+            *sample = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        }
+
+        offset += samples.len();
+    }
+
+    if offset != bytes.len() {
+        return Err(invalid("trailing data"));
+    }
+
+    chunk.updated = true;
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::terrain::generate_chunk_data;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("openworld-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        for mode in [MeshingMode::Blocky, MeshingMode::Smooth] {
+            let chunk = generate_chunk_data(2, -1, mode, 5);
+            let bytes = encode_chunk(&chunk);
+            let decoded = decode_chunk(&bytes, chunk.position).unwrap();
+
+            assert_eq!(
+                *decoded.blocks.as_ref().read(),
+                *chunk.blocks.as_ref().read()
+            );
+            assert_eq!(
+                *decoded.density.as_ref().read(),
+                *chunk.density.as_ref().read()
+            );
+        }
+    }
+
+    #[test]
+    fn uniform_chunks_stay_small() {
+        let chunk = Chunk::new(IVec3::ZERO);
+        chunk.blocks.as_ref().write().fill(2);
+
+        let bytes = encode_chunk(&chunk);
+        let runs = CHUNK_SIZE.pow(3).div_ceil(u16::MAX as usize);
+
+        assert_eq!(bytes.len(), 6 + runs * 3);
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        let chunk = generate_chunk_data(0, 0, MeshingMode::Blocky, 5);
+        let bytes = encode_chunk(&chunk);
+
+        assert!(decode_chunk(b"nope", IVec3::ZERO).is_err());
+        assert!(decode_chunk(&bytes[..bytes.len() - 1], IVec3::ZERO).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_chunk(&trailing, IVec3::ZERO).is_err());
+    }
+
+    #[test]
+    fn saves_chunks_and_info_to_folder() {
+        let dir = temp_dir("storage");
+        let chunk = generate_chunk_data(-3, 4, MeshingMode::Blocky, 9);
+
+        assert!(load_chunk(&dir, chunk.position).unwrap().is_none());
+        assert!(read_info(&dir).unwrap().is_none());
+
+        save_chunk(&dir, &chunk).unwrap();
+        let info = WorldInfo {
+            seed: 9,
+            meshing: MeshingMode::Blocky,
+            sky: Some(CubemapSource::Stacked("skies/day.png".to_string())),
+        };
+        write_info(&dir, &info).unwrap();
+
+        let loaded = load_chunk(&dir, chunk.position).unwrap().unwrap();
+        assert_eq!(loaded.position, chunk.position);
+        assert_eq!(
+            *loaded.blocks.as_ref().read(),
+            *chunk.blocks.as_ref().read()
+        );
+        assert_eq!(read_info(&dir).unwrap(), Some(info));
+
+        // worlds saved before skies were selectable keep the procedural one
+        fs::write(dir.join("world.json"), r#"{"seed":9,"meshing":"Blocky"}"#).unwrap();
+        assert_eq!(read_info(&dir).unwrap().unwrap().sky, None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use noise::NoiseFn;
+
+use super::chunk::*;
+
+fn terrain_height(noise: &noise::Perlin, x: f32, z: f32) -> f64 {
+    let mut npos: Vec2 = Vec2::new(x, z) / 100.0;
+
+    let h = noise.get([npos.x as f64, npos.y as f64]);
+
+    npos *= 0.50;
+    let factor = noise.get([npos.x as f64, npos.y as f64]);
+
+    (h * factor) * 32.0
+}
+
+/// Generates the blocks of the chunk column at `(x, z)`, and its density field for smooth
+/// meshing. The same seed always yields the same chunk.
+pub fn generate_chunk_data(x: i32, z: i32, mode: MeshingMode, seed: u32) -> Chunk {
+    let noise = noise::Perlin::new(seed);
+
+    let mut chunk = match mode {
+        MeshingMode::Blocky => Chunk::new(IVec3::new(x, 0, z)),
+        MeshingMode::Smooth => Chunk::with_density(IVec3::new(x, 0, z)),
+    };
+    let global_pos: Vec3 = Vec3::new(
+        x as f32 * CHUNK_SIZE as f32 + 0.5,
+        0.0,
+        z as f32 * CHUNK_SIZE as f32 + 0.5,
+    );
+
+    for cx in 0..CHUNK_SIZE {
+        for cz in 0..CHUNK_SIZE {
+            // the height only depends on the column
+            let h = terrain_height(&noise, cx as f32 + global_pos.x, cz as f32 + global_pos.z);
+
+            for cy in 0..CHUNK_SIZE {
+                let pos: Vec3 = Vec3::new(
+                    cx as f32 + global_pos.x,
+                    cy as f32,
+                    cz as f32 + global_pos.z,
+                );
+
+                chunk.set_block(
+                    cx,
+                    cy,
+                    cz,
+                    if pos.y - 16.0 < h as f32 {
+                        if pos.y < 10.0 {
+                            1
+                        } else {
+                            2
+                        }
+                    } else {
+                        0
+                    },
+                );
+            }
+        }
+    }
+
+    if mode == MeshingMode::Smooth {
+        for dx in 0..DENSITY_SIZE {
+            for dz in 0..DENSITY_SIZE {
+                let h = terrain_height(
+                    &noise,
+                    x as f32 * CHUNK_SIZE as f32 + dx as f32,
+                    z as f32 * CHUNK_SIZE as f32 + dz as f32,
+                );
+
+                for dy in 0..DENSITY_SIZE {
+                    chunk.set_density(dx, dy, dz, (h + 16.0) as f32 - dy as f32);
+                }
+            }
+        }
+    }
+
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_generates_same_chunk() {
+        let a = generate_chunk_data(3, -2, MeshingMode::Blocky, 42);
+        let b = generate_chunk_data(3, -2, MeshingMode::Blocky, 42);
+
+        assert_eq!(a.position, IVec3::new(3, 0, -2));
+        assert_eq!(*a.blocks.as_ref().read(), *b.blocks.as_ref().read());
+    }
+
+    #[test]
+    fn seeds_change_the_terrain() {
+        let a = generate_chunk_data(3, -2, MeshingMode::Blocky, 42);
+        let b = generate_chunk_data(3, -2, MeshingMode::Blocky, 43);
+
+        assert_ne!(*a.blocks.as_ref().read(), *b.blocks.as_ref().read());
+    }
+
+    #[test]
+    fn columns_are_water_then_grass_then_air() {
+        let chunk = generate_chunk_data(0, 0, MeshingMode::Blocky, 21744033);
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let column: Vec<u8> = (0..CHUNK_SIZE).map(|y| chunk.get_block(x, y, z)).collect();
+
+                let solid = column.iter().take_while(|block| **block != 0).count();
+                assert!(column[solid..].iter().all(|block| *block == 0));
+
+                for (y, block) in column[..solid].iter().enumerate() {
+                    assert_eq!(*block, if y < 10 { 1 } else { 2 });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn density_agrees_with_blocks() {
+        let chunk = generate_chunk_data(1, 1, MeshingMode::Smooth, 7);
+        let density = chunk.density.as_ref().read();
+
+        assert_eq!(density.len(), DENSITY_SIZE.pow(3));
+        assert!(generate_chunk_data(1, 1, MeshingMode::Blocky, 7)
+            .density
+            .as_ref()
+            .read()
+            .is_empty());
+
+        // the bottom corners are inside the terrain and the top ones above it
+        assert!(density[..DENSITY_SIZE].iter().all(|d| *d > 0.0));
+        let top = (DENSITY_SIZE - 1) * DENSITY_SIZE;
+        assert!(density[top..top + DENSITY_SIZE].iter().all(|d| *d < 0.0));
+    }
+}
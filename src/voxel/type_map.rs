@@ -9,7 +9,6 @@ where
         Self(Vec::new())
     }
 
-    #[allow(dead_code)]
     pub fn get(&self, key: &T) -> Option<&V> {
         self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
     }
@@ -40,7 +39,6 @@ where
         }
     }
 
-    #[allow(dead_code)]
     pub fn iter(&self) -> impl Iterator<Item = (&T, &V)> {
         self.0.iter().map(|(k, v)| (k, v))
     }
@@ -49,3 +47,58 @@ where
         self.0.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_replaces_existing_values() {
+        let mut map = Map::new();
+
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 3), Some(1));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"a"), Some(&3));
+        assert_eq!(map.get(&"b"), Some(&2));
+    }
+
+    #[test]
+    fn missing_keys() {
+        let mut map: Map<i32, i32> = Map::new();
+
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.get_mut(&1), None);
+        assert_eq!(map.remove(&1), None);
+        assert!(!map.contains_key(&1));
+    }
+
+    #[test]
+    fn get_mut_and_remove() {
+        let mut map = Map::new();
+        map.insert(1, String::from("one"));
+        map.insert(2, String::from("two"));
+
+        map.get_mut(&1).unwrap().push('!');
+        assert_eq!(map.get(&1).map(String::as_str), Some("one!"));
+
+        assert_eq!(map.remove(&1), Some(String::from("one!")));
+        assert!(!map.contains_key(&1));
+        assert!(map.contains_key(&2));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn iterates_in_insertion_order() {
+        let mut map = Map::new();
+        map.insert(3, 'c');
+        map.insert(1, 'a');
+        map.insert(2, 'b');
+        map.insert(1, 'z');
+
+        let entries: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(entries, vec![(3, 'c'), (1, 'z'), (2, 'b')]);
+    }
+}
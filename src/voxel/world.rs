@@ -1,24 +1,113 @@
 use std::{
     collections::LinkedList,
+    path::PathBuf,
     thread::{self},
 };
 
-use crate::player::Player;
+use crate::player::PlayerCamera;
 
+use super::block;
 use super::chunk::*;
+use super::history::{BlockChange, History};
+use super::storage;
+use super::terrain::generate_chunk_data;
 use super::type_map::*;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_rapier3d::prelude::*;
-use noise::NoiseFn;
 
 #[derive(Clone)]
 pub struct ChunkState {
     pub entity: Option<Entity>,
     pub mesh: Handle<Mesh>,
-    pub collider: Collider,
+    pub collider: Option<Collider>,
     pub chunk: Chunk,
     pub is_showing: bool,
+    pub lod: u32,
+    pub remeshing: bool,
+}
+
+/// Generated chunk, with its mesh and collider unless running headless, and detail level.
+pub type ChunkThread = thread::JoinHandle<(Chunk, Option<(Mesh, Option<Collider>)>, u32)>;
+
+/// Streams the chunks around an entity.
+#[derive(Component)]
+pub struct ChunkLoader {
+    /// Chunks loaded on each side of the chunk the entity is in.
+    pub radius: i32,
+    last_chunk: Option<IVec3>,
+}
+
+impl Default for ChunkLoader {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl ChunkLoader {
+    pub fn new(radius: i32) -> Self {
+        Self {
+            radius,
+            last_chunk: None,
+        }
+    }
+}
+
+/// Block hit by a ray, and the normal of the face the ray went through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayHit {
+    pub position: IVec3,
+    /// Zero when the ray starts inside the block.
+    pub normal: IVec3,
+}
+
+/// Walks the blocks a ray goes through in order, and returns the first one `hit` accepts
+/// within `max_distance`.
+pub fn raycast_blocks(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    mut hit: impl FnMut(IVec3) -> bool,
+) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut position = origin.floor().as_ivec3();
+    let step = direction.signum().as_ivec3();
+    // distance along the ray between two block borders, and to the next one, per axis
+    let delta = direction.recip().abs();
+    let mut next = Vec3::select(
+        direction.cmpgt(Vec3::ZERO),
+        (position.as_vec3() + 1.0 - origin) * delta,
+        (origin - position.as_vec3()) * delta,
+    );
+    let mut normal = IVec3::ZERO;
+
+    loop {
+        if hit(position) {
+            return Some(RayHit { position, normal });
+        }
+
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+
+        if next[axis] > max_distance {
+            return None;
+        }
+
+        position[axis] += step[axis];
+        next[axis] += delta[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
 }
 
 #[derive(Resource)]
@@ -26,7 +115,17 @@ pub struct World {
     pub chunks: Map<IVec3, ChunkState>,
     pub chunks_to_load: LinkedList<IVec3>,
     pub chunks_to_unload: LinkedList<IVec3>,
-    pub chunks_threads: LinkedList<thread::JoinHandle<(Chunk, Mesh, Collider)>>,
+    pub chunks_threads: LinkedList<ChunkThread>,
+    /// Chunks whose blocks were edited since they were last meshed.
+    pub chunks_to_remesh: LinkedList<IVec3>,
+    /// Distances from the camera at which chunks switch to the next coarser detail level.
+    pub lod_distances: [f32; LOD_LEVELS as usize - 1],
+    pub meshing: MeshingMode,
+    pub seed: u32,
+    /// Folder saved chunks are loaded from instead of being generated.
+    pub save_dir: Option<PathBuf>,
+    /// Block changes that can be undone.
+    pub history: History,
 }
 
 impl Default for World {
@@ -42,9 +141,239 @@ impl World {
             chunks_to_load: LinkedList::new(),
             chunks_to_unload: LinkedList::new(),
             chunks_threads: LinkedList::new(),
+            chunks_to_remesh: LinkedList::new(),
+            lod_distances: [128.0, 256.0, 384.0],
+            meshing: MeshingMode::default(),
+            seed: 21744033,
+            save_dir: None,
+            history: History::default(),
+        }
+    }
+
+    pub fn lod_at(&self, position: IVec3, camera: Vec3) -> u32 {
+        let center = Vec2::new(
+            (position.x as f32 + 0.5) * CHUNK_SIZE as f32,
+            (position.z as f32 + 0.5) * CHUNK_SIZE as f32,
+        );
+        let distance = center.distance(Vec2::new(camera.x, camera.z));
+
+        self.lod_distances
+            .iter()
+            .filter(|lod_distance| distance > **lod_distance)
+            .count() as u32
+    }
+
+    /// Block at a world position, `None` while the chunk holding it is not generated.
+    /// Everything above and below the chunks is air.
+    pub fn get_block(&self, position: IVec3) -> Option<u8> {
+        let size = CHUNK_SIZE as i32;
+
+        if position.y < 0 || position.y >= size {
+            return Some(block::AIR);
+        }
+
+        let chunk_pos = IVec3::new(position.x.div_euclid(size), 0, position.z.div_euclid(size));
+        let state = self.chunks.get(&chunk_pos)?;
+
+        // chunks still being generated only hold a placeholder
+        if !state.chunk.updated {
+            return None;
+        }
+
+        Some(state.chunk.get_block(
+            position.x.rem_euclid(size) as usize,
+            position.y as usize,
+            position.z.rem_euclid(size) as usize,
+        ))
+    }
+
+    /// Changes the block at a world position, recording the change in the history, and
+    /// queues its chunk to be remeshed. Returns the block it replaced, or `None` when the
+    /// chunk holding it is not generated.
+    ///
+    /// In smooth chunks the density at the block's corners follows it too, so the surface
+    /// grows around placed blocks and is carved away around removed ones.
+    pub fn set_block(&mut self, position: IVec3, block: u8) -> Option<u8> {
+        let previous = self.write_block(position, block)?;
+
+        if previous != block {
+            self.history.record(BlockChange {
+                position,
+                before: previous,
+                after: block,
+            });
+        }
+
+        Some(previous)
+    }
+
+    /// Reverts the last transaction of the history. Returns whether it was, which it is not
+    /// when there is none, or while a chunk it touches is not loaded.
+    pub fn undo(&mut self) -> bool {
+        if !self
+            .history
+            .next_undo()
+            .is_some_and(|changes| self.all_loaded(changes))
+        {
+            return false;
+        }
+
+        let Some(changes) = self.history.undo() else {
+            return false;
+        };
+
+        for change in changes {
+            self.write_block(change.position, change.after);
+        }
+
+        true
+    }
+
+    /// Applies the last undone transaction again. Returns whether it was, like `undo`.
+    pub fn redo(&mut self) -> bool {
+        if !self
+            .history
+            .next_redo()
+            .is_some_and(|changes| self.all_loaded(changes))
+        {
+            return false;
+        }
+
+        let Some(changes) = self.history.redo() else {
+            return false;
+        };
+
+        for change in changes {
+            self.write_block(change.position, change.after);
+        }
+
+        true
+    }
+
+    /// Whether the chunks of all the changes are loaded, so none of them would be lost.
+    fn all_loaded(&self, changes: &[BlockChange]) -> bool {
+        changes
+            .iter()
+            .all(|change| self.get_block(change.position).is_some())
+    }
+
+    /// Changes a block without recording it in the history.
+    fn write_block(&mut self, position: IVec3, block: u8) -> Option<u8> {
+        let size = CHUNK_SIZE as i32;
+
+        if position.y < 0 || position.y >= size {
+            return None;
+        }
+
+        let chunk_pos = IVec3::new(position.x.div_euclid(size), 0, position.z.div_euclid(size));
+        let state = self
+            .chunks
+            .get_mut(&chunk_pos)
+            .filter(|state| state.chunk.updated)?;
+
+        let (x, y, z) = (
+            position.x.rem_euclid(size) as usize,
+            position.y as usize,
+            position.z.rem_euclid(size) as usize,
+        );
+        let previous = state.chunk.get_block(x, y, z);
+
+        if previous != block {
+            state.chunk.set_block(x, y, z, block);
+
+            if !self.chunks_to_remesh.contains(&chunk_pos) {
+                self.chunks_to_remesh.push_back(chunk_pos);
+            }
+
+            let density = if block::is_solid(block) { 1.0 } else { -1.0 };
+            for dx in [0, 1] {
+                for dy in [0, 1] {
+                    for dz in [0, 1] {
+                        self.write_density(position + IVec3::new(dx, dy, dz), density);
+                    }
+                }
+            }
+        }
+
+        Some(previous)
+    }
+
+    /// Sets the density at a block corner in every smooth chunk sampling it, since chunks
+    /// also keep the samples along the borders of their +x and +z neighbours.
+    fn write_density(&mut self, corner: IVec3, density: f32) {
+        let size = CHUNK_SIZE as i32;
+        let reach = DENSITY_SIZE as i32 - 1;
+
+        for chunk_x in (corner.x - reach).div_euclid(size)..=corner.x.div_euclid(size) {
+            for chunk_z in (corner.z - reach).div_euclid(size)..=corner.z.div_euclid(size) {
+                let chunk_pos = IVec3::new(chunk_x, 0, chunk_z);
+                let (x, z) = (corner.x - chunk_x * size, corner.z - chunk_z * size);
+
+                if x > reach || z > reach {
+                    continue;
+                }
+
+                let Some(state) = self.chunks.get_mut(&chunk_pos) else {
+                    continue;
+                };
+
+                if state.chunk.density.as_ref().read().is_empty() {
+                    continue;
+                }
+
+                state
+                    .chunk
+                    .set_density(x as usize, corner.y as usize, z as usize, density);
+
+                if !self.chunks_to_remesh.contains(&chunk_pos) {
+                    self.chunks_to_remesh.push_back(chunk_pos);
+                }
+            }
         }
     }
 
+    /// First solid block along a ray within `max_distance`.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        raycast_blocks(origin, direction, max_distance, |position| {
+            self.get_block(position).is_some_and(block::is_solid)
+        })
+    }
+
+    /// Block containing a point.
+    pub fn block_at(&self, point: Vec3) -> Option<u8> {
+        self.get_block(point.floor().as_ivec3())
+    }
+
+    /// Height of the top of the highest solid block in the column at `(x, z)`, or the top
+    /// of the chunk when the column has no ground. `None` while the chunk holding it has no
+    /// collider yet.
+    pub fn ground_height(&self, x: i32, z: i32) -> Option<f32> {
+        let size = CHUNK_SIZE as i32;
+        let state = self.collided_chunk(x, z)?;
+        let (x, z) = (x.rem_euclid(size) as usize, z.rem_euclid(size) as usize);
+
+        let ground = (0..CHUNK_SIZE)
+            .rev()
+            .find(|y| block::is_solid(state.chunk.get_block(x, *y, z)))
+            .map_or(CHUNK_SIZE, |y| y + 1);
+
+        Some(ground as f32)
+    }
+
+    /// Whether the chunk holding the column at `(x, z)` has its collider, so things can be
+    /// placed there without falling through.
+    pub fn has_collider(&self, x: i32, z: i32) -> bool {
+        self.collided_chunk(x, z).is_some()
+    }
+
+    fn collided_chunk(&self, x: i32, z: i32) -> Option<&ChunkState> {
+        let size = CHUNK_SIZE as i32;
+
+        self.chunks
+            .get(&IVec3::new(x.div_euclid(size), 0, z.div_euclid(size)))
+            .filter(|state| state.chunk.updated && state.collider.is_some())
+    }
+
     pub fn load_chunk(&mut self, position: IVec3) {
         let pos = self.chunks_to_load.iter().position(|x| x.eq(&position));
 
@@ -60,22 +389,48 @@ pub fn startup(mut world: ResMut<World>) {
     world.load_chunk(IVec3::new(0, 0, 0));
 }
 
+pub fn stream_chunks(
+    mut world: ResMut<World>,
+    mut loaders_query: Query<(&Transform, &mut ChunkLoader)>,
+) {
+    for (transform, mut loader) in loaders_query.iter_mut() {
+        let chunk_pos = IVec3::new(
+            (transform.translation.x / CHUNK_SIZE as f32).floor() as i32,
+            0,
+            (transform.translation.z / CHUNK_SIZE as f32).floor() as i32,
+        );
+
+        if loader.last_chunk == Some(chunk_pos) {
+            continue;
+        }
+
+        for x in -loader.radius..=loader.radius {
+            for z in -loader.radius..=loader.radius {
+                world.load_chunk(chunk_pos + IVec3::new(x, 0, z));
+            }
+        }
+
+        loader.last_chunk = Some(chunk_pos);
+    }
+}
+
 pub fn update(
     mut commands: Commands,
     mut chunks_query: Query<(&mut Chunk, Entity)>,
-    mut player_query: Query<&mut Transform, With<Player>>,
+    loaders_query: Query<&Transform, With<ChunkLoader>>,
     mut world: ResMut<World>,
 ) {
-    if player_query.is_empty() {
+    if loaders_query.is_empty() {
         return;
     }
 
-    let transform = player_query.single_mut();
-
     for (chunk, entity) in chunks_query.iter_mut() {
         let pos = chunk.get_world_position();
 
-        if pos.distance(transform.translation) > 32.0 * 15.0 {
+        if loaders_query
+            .iter()
+            .all(|transform| pos.distance(transform.translation) > 32.0 * 15.0)
+        {
             let pos = chunk.position;
 
             if let Some(state) = world.chunks.get_mut(&pos) {
@@ -92,13 +447,22 @@ pub fn update(
 pub fn load_chunks(
     mut world: ResMut<World>,
     mut commands: Commands,
-    mut asset_server: ResMut<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Option<ResMut<AssetServer>>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
 ) {
     if world.chunks_to_load.is_empty() {
         return;
     }
 
+    let camera = camera_query
+        .get_single()
+        .map_or(Vec3::ZERO, |transform| transform.translation());
+
+    // without a renderer chunks are only generated, never meshed nor spawned
+    let mut render = asset_server.zip(materials);
+    let headless = render.is_none();
+
     while world.chunks_to_load.len() > 0 {
         let chunk_pos = world.chunks_to_load.pop_front().unwrap();
 
@@ -110,12 +474,14 @@ pub fn load_chunks(
                     continue;
                 }
 
-                chunk_state.entity = Some(spawn_chunk(
-                    &mut commands,
-                    &mut asset_server,
-                    &mut materials,
-                    chunk_state.clone(),
-                ));
+                if let Some((asset_server, materials)) = render.as_mut() {
+                    chunk_state.entity = Some(spawn_chunk(
+                        &mut commands,
+                        asset_server,
+                        materials,
+                        chunk_state.clone(),
+                    ));
+                }
                 chunk_state.is_showing = true;
 
                 world.chunks.insert(chunk_state.chunk.position, chunk_state);
@@ -124,11 +490,24 @@ pub fn load_chunks(
             continue;
         }
 
+        let lod = world.lod_at(chunk_pos, camera);
+        let mode = world.meshing;
+        let seed = world.seed;
+        let save_dir = world.save_dir.clone();
+
         let handle = thread::spawn(move || {
-            let mut chunk = generate_chunk_data(chunk_pos.x, chunk_pos.z);
-            let (mesh, collider) = build_chunk_mesh(&chunk);
+            let saved = save_dir.and_then(|dir| {
+                storage::load_chunk(&dir, chunk_pos).unwrap_or_else(|error| {
+                    warn!("could not load chunk {}: {}", chunk_pos, error);
+                    None
+                })
+            });
+
+            let mut chunk =
+                saved.unwrap_or_else(|| generate_chunk_data(chunk_pos.x, chunk_pos.z, mode, seed));
+            let mesh = (!headless).then(|| build_mesh(&chunk, mode, lod).build());
             chunk.updated = true;
-            (chunk, mesh, collider)
+            (chunk, mesh, lod)
         });
 
         world.chunks.insert(
@@ -136,9 +515,11 @@ pub fn load_chunks(
             ChunkState {
                 entity: None,
                 mesh: Handle::default(),
-                collider: Collider::default(),
+                collider: None,
                 chunk: Chunk::new(chunk_pos),
                 is_showing: false,
+                lod,
+                remeshing: true,
             },
         );
 
@@ -166,18 +547,18 @@ pub fn unload_chunks(mut commands: Commands, mut world: ResMut<World>) {
 }
 
 pub fn generate_chunks(
-    mut meshes: ResMut<Assets<Mesh>>,
+    meshes: Option<ResMut<Assets<Mesh>>>,
     mut world: ResMut<World>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut asset_server: ResMut<AssetServer>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+    asset_server: Option<ResMut<AssetServer>>,
     mut commands: Commands,
 ) {
     if world.chunks_threads.is_empty() {
         return;
     }
 
-    let mut threads_unfinished: LinkedList<thread::JoinHandle<(Chunk, Mesh, Collider)>> =
-        LinkedList::new();
+    let mut threads_unfinished: LinkedList<ChunkThread> = LinkedList::new();
+    let mut render = meshes.zip(materials).zip(asset_server);
 
     while world.chunks_threads.len() > 0 {
         let thread = world.chunks_threads.pop_front().unwrap();
@@ -187,22 +568,39 @@ pub fn generate_chunks(
             break;
         }
 
-        let (chunk, mesh, collider) = thread.join().unwrap();
+        let (chunk, mesh, lod) = thread.join().unwrap();
+
+        // a remeshed chunk replaces the entity showing its previous detail level
+        if let Some(entity) = world
+            .chunks
+            .get(&chunk.position)
+            .and_then(|state| state.entity)
+        {
+            commands.entity(entity).despawn_recursive();
+        }
 
         let mut chunk_state = ChunkState {
             entity: None,
-            mesh: meshes.add(mesh),
-            collider,
+            mesh: Handle::default(),
+            collider: None,
             chunk,
             is_showing: true,
+            lod,
+            remeshing: false,
         };
 
-        chunk_state.entity = Some(spawn_chunk(
-            &mut commands,
-            &mut asset_server,
-            &mut materials,
-            chunk_state.clone(),
-        ));
+        if let (Some((mesh, collider)), Some(((meshes, materials), asset_server))) =
+            (mesh, render.as_mut())
+        {
+            chunk_state.mesh = meshes.add(mesh);
+            chunk_state.collider = collider;
+            chunk_state.entity = Some(spawn_chunk(
+                &mut commands,
+                asset_server,
+                materials,
+                chunk_state.clone(),
+            ));
+        }
 
         world.chunks.insert(chunk_state.chunk.position, chunk_state);
     }
@@ -210,56 +608,75 @@ pub fn generate_chunks(
     world.chunks_threads.append(&mut threads_unfinished);
 }
 
-fn generate_chunk_data(x: i32, z: i32) -> Chunk {
-    let noise = noise::Perlin::new(21744033);
+/// Remeshes the visible chunks whose detail level no longer matches their distance to the
+/// camera.
+pub fn update_lods(
+    mut world: ResMut<World>,
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    let camera = camera.translation();
+    let mut remesh = vec![];
+
+    for (position, state) in world.chunks.iter() {
+        if !state.is_showing || state.remeshing {
+            continue;
+        }
+
+        let lod = world.lod_at(*position, camera);
+
+        if lod != state.lod {
+            remesh.push((*position, lod));
+        }
+    }
+
+    let mode = world.meshing;
+
+    for (position, lod) in remesh {
+        let state = world.chunks.get_mut(&position).unwrap();
+        let chunk = state.chunk.clone();
+        state.remeshing = true;
+
+        world.chunks_threads.push_back(thread::spawn(move || {
+            let mesh = build_mesh(&chunk, mode, lod).build();
+            (chunk, Some(mesh), lod)
+        }));
+    }
+}
+
+/// Remeshes the chunks whose blocks were edited, and saves them when the world has a save
+/// folder.
+pub fn remesh_chunks(mut world: ResMut<World>) {
+    let mode = world.meshing;
 
-    let mut chunk = Chunk::new(IVec3::new(x, 0, z));
-    let global_pos: Vec3 = Vec3::new(
-        x as f32 * CHUNK_SIZE as f32 + 0.5,
-        0.0,
-        z as f32 * CHUNK_SIZE as f32 + 0.5,
-    );
+    while let Some(position) = world.chunks_to_remesh.pop_front() {
+        let save_dir = world.save_dir.clone();
 
-    for cx in 0..CHUNK_SIZE {
-        for cy in 0..CHUNK_SIZE {
-            for cz in 0..CHUNK_SIZE {
-                let pos: Vec3 = Vec3::new(
-                    cx as f32 + global_pos.x,
-                    cy as f32,
-                    cz as f32 + global_pos.z,
-                );
-
-                let mut npos: Vec2 = Vec2::new(pos.x, pos.z) / 100.0;
-
-                let mut h = noise.get([npos.x as f64, npos.y as f64]);
-
-                npos *= 0.50;
-                let factor = noise.get([npos.x as f64, npos.y as f64]);
-
-                h = (h * factor) * 32.0;
-
-                chunk.set_block(
-                    cx,
-                    cy,
-                    cz,
-                    if pos.y - 16.0 < h as f32 {
-                        if pos.y < 10.0 {
-                            1
-                        } else {
-                            2
-                        }
-                    } else {
-                        0
-                    },
-                );
+        let Some(state) = world.chunks.get_mut(&position) else {
+            continue;
+        };
+
+        let chunk = state.chunk.clone();
+        let lod = state.lod;
+        state.remeshing = true;
+
+        if let Some(dir) = save_dir {
+            if let Err(error) = storage::save_chunk(&dir, &chunk) {
+                warn!("could not save chunk {}: {}", position, error);
             }
         }
-    }
 
-    chunk
+        world.chunks_threads.push_back(thread::spawn(move || {
+            let mesh = build_mesh(&chunk, mode, lod).build();
+            (chunk, Some(mesh), lod)
+        }));
+    }
 }
 
-pub fn debug(world: ResMut<World>, mut contexts: EguiContexts) {
+pub fn debug(mut world: ResMut<World>, mut contexts: EguiContexts) {
     egui::Window::new("World Diagnostics").show(&contexts.ctx_mut(), |ui| {
         ui.label(format!("Chunks: {}", world.chunks.len()));
         ui.label(format!("Chunks to load: {}", world.chunks_to_load.len()));
@@ -268,5 +685,239 @@ pub fn debug(world: ResMut<World>, mut contexts: EguiContexts) {
             "Chunks to unload: {}",
             world.chunks_to_unload.len()
         ));
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(world.history.can_undo(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                world.undo();
+            }
+            if ui
+                .add_enabled(world.history.can_redo(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                world.redo();
+            }
+        });
     });
 }
+
+#[cfg(test)]
+impl World {
+    /// Adds a chunk as if it was generated and loaded, without a mesh or collider.
+    pub fn insert_loaded_chunk(&mut self, mut chunk: Chunk) -> &mut ChunkState {
+        let position = chunk.position;
+        chunk.updated = true;
+
+        self.chunks.insert(
+            position,
+            ChunkState {
+                entity: None,
+                mesh: Handle::default(),
+                collider: None,
+                chunk,
+                is_showing: true,
+                lod: 0,
+                remeshing: false,
+            },
+        );
+
+        self.chunks.get_mut(&position).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_found_across_chunks() {
+        let mut world = World::new();
+
+        let mut chunk = Chunk::new(IVec3::new(-1, 0, 2));
+        chunk.set_block(CHUNK_SIZE - 1, 3, 0, block::WATER);
+        world.insert_loaded_chunk(chunk);
+
+        let size = CHUNK_SIZE as i32;
+        assert_eq!(
+            world.get_block(IVec3::new(-1, 3, 2 * size)),
+            Some(block::WATER)
+        );
+        assert_eq!(
+            world.get_block(IVec3::new(-2, 3, 2 * size)),
+            Some(block::AIR)
+        );
+        assert_eq!(
+            world.block_at(Vec3::new(-0.5, 3.9, 2.0 * size as f32 + 0.2)),
+            Some(block::WATER)
+        );
+
+        // outside the chunks, and in a chunk that is not there
+        assert_eq!(
+            world.get_block(IVec3::new(-1, -1, 2 * size)),
+            Some(block::AIR)
+        );
+        assert_eq!(world.get_block(IVec3::new(0, 3, 0)), None);
+    }
+
+    #[test]
+    fn ground_height_is_the_top_of_the_highest_solid_block() {
+        let mut world = World::new();
+
+        let mut chunk = Chunk::new(IVec3::new(-1, 0, 2));
+        chunk.set_block(CHUNK_SIZE - 1, 1, 0, block::GRASS);
+        chunk.set_block(CHUNK_SIZE - 1, 5, 0, block::WATER);
+        world.insert_loaded_chunk(chunk);
+
+        // the chunk has no collider yet
+        let size = CHUNK_SIZE as i32;
+        assert_eq!(world.ground_height(-1, 2 * size), None);
+
+        world
+            .chunks
+            .get_mut(&IVec3::new(-1, 0, 2))
+            .unwrap()
+            .collider = Some(Collider::ball(1.0));
+
+        // water is not ground, and a column without any stands at the top of the chunk
+        assert_eq!(world.ground_height(-1, 2 * size), Some(2.0));
+        assert_eq!(world.ground_height(-2, 2 * size), Some(CHUNK_SIZE as f32));
+    }
+
+    #[test]
+    fn edits_queue_their_chunk_once() {
+        let mut world = World::new();
+
+        let mut chunk = Chunk::new(IVec3::new(-1, 0, 2));
+        chunk.set_block(CHUNK_SIZE - 1, 1, 0, block::GRASS);
+        world.insert_loaded_chunk(chunk);
+
+        let position = IVec3::new(-1, 1, 2 * CHUNK_SIZE as i32);
+        assert_eq!(world.set_block(position, block::AIR), Some(block::GRASS));
+        assert_eq!(
+            world.set_block(position + IVec3::Y, block::GRASS),
+            Some(block::AIR)
+        );
+        assert_eq!(world.get_block(position + IVec3::Y), Some(block::GRASS));
+        assert_eq!(world.chunks_to_remesh.len(), 1);
+
+        // in a chunk that is not there
+        assert_eq!(world.set_block(IVec3::new(0, 1, 0), block::GRASS), None);
+        assert_eq!(world.chunks_to_remesh.len(), 1);
+    }
+
+    #[test]
+    fn edits_are_undone_and_redone() {
+        let mut world = World::new();
+        world.insert_loaded_chunk(Chunk::new(IVec3::ZERO));
+
+        let position = IVec3::new(3, 1, 4);
+        world.set_block(position, block::GRASS);
+        world.set_block(position + IVec3::Y, block::WATER);
+
+        // as they were made
+        assert!(world.undo());
+        assert_eq!(world.get_block(position + IVec3::Y), Some(block::AIR));
+        assert_eq!(world.get_block(position), Some(block::GRASS));
+        assert!(world.undo());
+        assert_eq!(world.get_block(position), Some(block::AIR));
+        assert!(!world.undo());
+
+        assert!(world.redo());
+        assert_eq!(world.get_block(position), Some(block::GRASS));
+        assert_eq!(world.get_block(position + IVec3::Y), Some(block::AIR));
+
+        // not while a chunk they touch is unloaded, which would revert only part of them
+        let (neighbour, other) = (IVec3::new(-1, 0, 0), IVec3::new(-1, 1, 0));
+        world.insert_loaded_chunk(Chunk::new(neighbour));
+        world.set_blocks([(position, block::WATER), (other, block::GRASS)]);
+        let unloaded = world.chunks.remove(&neighbour).unwrap();
+
+        assert!(!world.undo());
+        assert_eq!(world.get_block(position), Some(block::WATER));
+
+        world.chunks.insert(neighbour, unloaded);
+        assert!(world.undo());
+        assert_eq!(world.get_block(position), Some(block::GRASS));
+        assert_eq!(world.get_block(other), Some(block::AIR));
+    }
+
+    #[test]
+    fn edits_reshape_smooth_chunks() {
+        let mut world = World::new();
+
+        for chunk_pos in [IVec3::ZERO, IVec3::new(-1, 0, 0)] {
+            let mut chunk = Chunk::with_density(chunk_pos);
+            for x in 0..DENSITY_SIZE {
+                for y in 0..DENSITY_SIZE {
+                    for z in 0..DENSITY_SIZE {
+                        chunk.set_density(x, y, z, 20.5 - y as f32);
+                    }
+                }
+            }
+            world.insert_loaded_chunk(chunk);
+        }
+
+        let mesh = |world: &World| {
+            build_chunk_mesh_smooth(&world.chunks.get(&IVec3::ZERO).unwrap().chunk, 0)
+        };
+        let flat = mesh(&world);
+
+        // a block floating above the surface, on the border with the -x chunk
+        world.set_block(IVec3::new(0, 30, 5), block::GRASS);
+        let raised = mesh(&world);
+        assert!(raised.indices.len() > flat.indices.len());
+        assert!(raised.positions.iter().any(|p| p[1] > 29.0));
+
+        let density = |chunk: IVec3, x: usize, y: usize, z: usize| {
+            world
+                .chunks
+                .get(&chunk)
+                .unwrap()
+                .chunk
+                .density
+                .as_ref()
+                .read()[x + y * DENSITY_SIZE + z * DENSITY_SIZE * DENSITY_SIZE]
+        };
+        assert!(density(IVec3::new(-1, 0, 0), CHUNK_SIZE, 30, 5) > 0.0);
+        assert!(density(IVec3::new(-1, 0, 0), CHUNK_SIZE + 1, 31, 6) > 0.0);
+        assert_eq!(world.chunks_to_remesh.len(), 2);
+
+        // removing it flattens the surface again
+        world.set_block(IVec3::new(0, 30, 5), block::AIR);
+        assert_eq!(mesh(&world).positions, flat.positions);
+    }
+
+    #[test]
+    fn rays_stop_at_the_first_block_hit() {
+        let wall = |position: IVec3| position.x == 5;
+
+        let hit = raycast_blocks(Vec3::new(0.5, 0.5, 0.5), Vec3::X, 10.0, wall).unwrap();
+        assert_eq!(hit.position, IVec3::new(5, 0, 0));
+        assert_eq!(hit.normal, -IVec3::X);
+
+        // diagonal rays go through every block they touch
+        let mut visited = vec![];
+        raycast_blocks(
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(1.0, 1.0, 0.0),
+            3.0,
+            |p| {
+                visited.push(p);
+                false
+            },
+        );
+        for pair in visited.windows(2) {
+            let step = (pair[1] - pair[0]).abs();
+            assert_eq!(step.x + step.y + step.z, 1);
+        }
+
+        // coming from above, and out of reach
+        let hit = raycast_blocks(Vec3::new(0.5, 9.5, 0.5), -Vec3::Y, 10.0, |p| p.y == 2).unwrap();
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!(raycast_blocks(Vec3::new(0.5, 0.5, 0.5), Vec3::X, 3.0, wall).is_none());
+    }
+}